@@ -1,8 +1,17 @@
 mod messages;
 use bioenpro4to_channel_manager::channels::root_channel::RootChannel;
-use bioenpro4to_channel_manager::channels::{Category, ChannelInfo, DailyChannelManager};
-use bioenpro4to_channel_manager::utils::{create_encryption_key, create_encryption_nonce, current_time_secs};
+use bioenpro4to_channel_manager::channels::{Category, ChannelInfo, DailyChannelManager, NetworkConfig, FileKeystore, Keystore, PayloadEncoding, MessageReader,
+                                                   Subscription, SubscriptionOptions, RollingDailyChannel};
+use bioenpro4to_channel_manager::utils::{create_encryption_key, create_encryption_nonce, current_time_secs, NaiveDate, Tz};
+use bioenpro4to_channel_manager::transport::{Transport, TangleTransport, InMemoryTransport};
 use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use std::time::Duration;
+use futures::StreamExt;
+use messages::trucks::TruckMessageGenerator;
+use messages::scales::ScaleMessageGenerator;
+use messages::biocells::BiocellMessageGenerator;
+use bioenpro4to_channel_manager::messages::{ReferenceResolver, ChannelReference};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct Message{
@@ -13,62 +22,185 @@ impl Message{
     fn new(msg: &str) -> Self {
         Message { msg: msg.to_string(), timestamp: current_time_secs() }
     }
+}
 
-    fn to_json(&self) -> anyhow::Result<Vec<u8>>{
-        Ok(serde_json::to_string(self)?.as_bytes().to_vec())
-    }
+fn date(day: u32, month: u32, year: i32) -> NaiveDate{
+    NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
 }
 
-async fn test_create_nested_channels(state_psw: &str, mainnet: bool, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<ChannelInfo>{
-    let mut root = RootChannel::new(mainnet);
+async fn test_create_nested_channels(state_psw: &str, transport: Arc<dyn Transport>, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<ChannelInfo>{
+    // Daily channels follow the days of the plant, wherever the tree is managed from
+    let timezone = "Europe/Rome".parse::<Tz>().map_err(anyhow::Error::msg)?;
+    let mut root = RootChannel::new_with_timezone(transport.clone(), Category::defaults(), timezone);
     let info = root.open(state_psw).await?;
     let state_psw = "psw2";
-    root.new_daily_actor_channel(Category::Trucks, "XASD", state_psw, 24, 9, 2021).await?;
-    root.new_daily_actor_channel(Category::Trucks, "XASD", state_psw, 23, 9, 2021).await?;
-    root.new_daily_actor_channel(Category::Trucks, "XASD2", state_psw, 24, 9, 2021).await?;
-    let mut scale_ch = root.new_daily_actor_channel(Category::Scales, "SCALE1", state_psw, 24, 9, 2021).await?;
+    root.new_daily_actor_channel(Category::trucks(), "XASD", state_psw, date(24, 9, 2021)).await?;
+    root.new_daily_actor_channel(Category::trucks(), "XASD", state_psw, date(23, 9, 2021)).await?;
+    root.new_daily_actor_channel(Category::trucks(), "XASD2", state_psw, date(24, 9, 2021)).await?;
+    let mut scale_ch = root.new_daily_actor_channel(Category::scales(), "SCALE1", state_psw, date(24, 9, 2021)).await?;
+    let tanks = Category::new("digestate_tanks", "Digestate Tanks", "Tanks");
+    root.add_category(tanks).await?;
+    // Custom categories are resolved by the tree that registered them
+    root.new_daily_actor_channel(root.category("digestate_tanks")?, "TANK1", state_psw, date(24, 9, 2021)).await?;
 
-    root.get_daily_actor_channel(Category::Trucks, "XASD", state_psw, 24, 9, 2021).await?;
-    let state = root.serialize_daily_actor_channel(Category::Trucks, "XASD", state_psw, 24, 9, 2021).await?;
-    let mut daily_ch = DailyChannelManager::import_from_base64(&state, state_psw).await?;
+    root.get_daily_actor_channel(Category::trucks(), "XASD", state_psw, date(24, 9, 2021)).await?;
+    let state = root.serialize_daily_actor_channel(Category::trucks(), "XASD", state_psw, date(24, 9, 2021)).await?;
+    let mut daily_ch = DailyChannelManager::import_from_base64_with_transport(&state, state_psw, transport.clone()).await?;
 
     let public = Message::new("PUBLIC MESSAGE");
     let private = Message::new("PRIVATE MESSAGE");
-    daily_ch.send_raw_packet(public.to_json()?, private.to_json()?, key_nonce).await?;
-    scale_ch.send_raw_packet(public.to_json()?, private.to_json()?, key_nonce).await?;
-    root.print_nested_channel_info();
+    daily_ch.send(&public, &private, key_nonce).await?;
+    scale_ch.set_encoding(PayloadEncoding::Cbor);
+    scale_ch.send(&public, &private, key_nonce).await?;
+
+    // Typed messages are validated and accepted only by the channels of their category
+    let mut trucks = TruckMessageGenerator::new("AB123CD".to_string(), "Mario Rossi".to_string());
+    let truck_msg_id = daily_ch.send_typed(&trucks.next()?, &private, key_nonce).await?;
+    let truck_ch = daily_ch.channel_info();
+    let mut scales = ScaleMessageGenerator::new(
+        truck_ch.channel_id().to_string(), truck_ch.announce_id().to_string(), truck_msg_id,
+        "Plant".to_string(), "AB123CD".to_string(), "Mario Rossi".to_string()
+    );
+    assert!(scale_ch.send_typed(&trucks.next()?, &private, key_nonce).await.is_err());
+    let scale_msg_id = scale_ch.send_typed(&scales.next()?, &private, key_nonce).await?;
+
+    // The biocell message refers to the weighing, that refers to the truck delivery
+    let scale_info = scale_ch.channel_info();
+    let mut biocells = BiocellMessageGenerator::new(
+        scale_info.channel_id().to_string(), scale_info.announce_id().to_string(), scale_msg_id,
+        "Plant".to_string(), "DIGESTOR1".to_string()
+    );
+    let biocell_msg = biocells.next()?;
+    let mut biocell_ch = root.new_daily_actor_channel(Category::biocells(), "BIO2", state_psw, date(24, 9, 2021)).await?;
+    let biocell_msg_id = biocell_ch.send_typed(&biocell_msg, &private, key_nonce).await?;
+    let mut resolver = ReferenceResolver::new_with_transport(transport.clone()).key_nonce(key_nonce);
+    let chain = resolver.trace_provenance(&biocell_msg).await?;
+    println!("Provenance of {}: weighing {} of {} kg, delivery {} by {}", biocell_msg.digestor_id(),
+             chain.scale.message.link(), chain.scale.decoded.truck_info().weight(),
+             chain.truck.message.link(), chain.truck.decoded.driver());
+    let biocell_info = biocell_ch.channel_info();
+    let biocell_ref = ChannelReference::new(biocell_info.channel_id().to_string(), biocell_info.announce_id().to_string(), biocell_msg_id);
+    let report = resolver.verify_provenance(&root, &biocell_ref).await;
+    println!("Provenance verification report:\n{}", report.to_json()?);
+    root.print_nested_channel_info().await;
     Ok(info)
 }
 
-async fn test_restore_nested_channels(info: ChannelInfo, state_psw: &str, mainnet: bool, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<()>{
+async fn test_restore_nested_channels(info: ChannelInfo, state_psw: &str, transport: Arc<dyn Transport>, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<()>{
     let channel_id = info.channel_id();
     let announce_id = info.announce_id();
-    let mut root = RootChannel::import_from_tangle(
+    let root = RootChannel::import_from_tangle_with_transport(
         channel_id,
         announce_id,
         state_psw,
-        mainnet
+        transport.clone()
     ).await?;
     let state_psw = "psw2";
-    root.new_daily_actor_channel(Category::Trucks, "XASD3", state_psw, 24, 9, 2021).await?;
-    let mut daily_ch = root.get_daily_actor_channel(Category::Trucks, "XASD", state_psw, 24, 9, 2021).await?;
-    let mut biocell_ch = root.new_daily_actor_channel(Category::BioCells, "BIO1", state_psw, 24, 9, 2021).await?;
+    root.new_daily_actor_channel(Category::trucks(), "XASD3", state_psw, date(24, 9, 2021)).await?;
+    let mut daily_ch = root.get_daily_actor_channel(Category::trucks(), "XASD", state_psw, date(24, 9, 2021)).await?;
+    let mut biocell_ch = root.new_daily_actor_channel(Category::biocells(), "BIO1", state_psw, date(24, 9, 2021)).await?;
     let public = Message::new("PUBLIC MESSAGE");
     let private = Message::new("PRIVATE MESSAGE");
-    daily_ch.send_raw_packet(public.to_json()?, private.to_json()?, key_nonce).await?;
-    biocell_ch.send_raw_packet(public.to_json()?, private.to_json()?, key_nonce).await?;
-    root.print_nested_channel_info();
+    daily_ch.send(&public, &private, key_nonce).await?;
+    biocell_ch.set_encoding(PayloadEncoding::MessagePack);
+    biocell_ch.send(&public, &private, key_nonce).await?;
+    let reader = MessageReader::new_with_key_nonce(&biocell_ch.channel_info(), transport, key_nonce).await?;
+    for msg in reader.msgs() {
+        println!("BioCell message {}: public = {:?}, masked = {:?}", msg.link(), msg.public_as::<Message>()?, msg.masked_as::<Message>()?);
+    }
+    root.print_nested_channel_info().await;
+    Ok(())
+}
+
+async fn test_subscription(info: ChannelInfo, state_psw: &str, transport: Arc<dyn Transport>, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<()>{
+    let root = RootChannel::import_from_tangle_with_transport(info.channel_id(), info.announce_id(), state_psw, transport.clone()).await?;
+    let mut biocell_ch = root.get_daily_actor_channel(Category::biocells(), "BIO1", "psw2", date(24, 9, 2021)).await?;
+    let options = SubscriptionOptions::new()
+        .poll_interval(Duration::from_secs(1))
+        .key_nonce(key_nonce);
+    let mut subscription = Subscription::new_with_transport(&biocell_ch.channel_info(), transport.clone(), options.clone())?;
+    let first = subscription.next().await.expect("subscriptions never end")?;
+    println!("Subscription delivered {} at position {}", first.message.link(), first.cursor.position());
+
+    // A consumer restarted from the saved cursor receives only the new messages
+    biocell_ch.send(&Message::new("NEW PUBLIC MESSAGE"), &Message::new("NEW PRIVATE MESSAGE"), key_nonce).await?;
+    let options = options.resume_from(first.cursor);
+    let mut subscription = Subscription::new_with_transport(&biocell_ch.channel_info(), transport, options)?;
+    let next = subscription.next().await.expect("subscriptions never end")?;
+    println!("Subscription resumed with {:?} at position {}", next.message.public_as::<Message>()?, next.cursor.position());
+    Ok(())
+}
+
+async fn test_backup_and_restore(info: ChannelInfo, state_psw: &str, transport: Arc<dyn Transport>) -> anyhow::Result<()>{
+    let root = RootChannel::import_from_tangle_with_transport(info.channel_id(), info.announce_id(), state_psw, transport.clone()).await?;
+    let backup = root.export_backup(state_psw).await?;
+    println!("Daily channels left out of the backup: {}", backup.missing_daily_channels.len());
+    let restored = RootChannel::restore_backup_with_transport(&backup.bytes, state_psw, transport, true).await?;
+    restored.print_nested_channel_info().await;
+    Ok(())
+}
+
+async fn test_keystore(info: ChannelInfo, state_psw: &str, transport: Arc<dyn Transport>) -> anyhow::Result<()>{
+    let path = std::env::temp_dir().join("bioenpro4to_keystore");
+    let mut keystore = FileKeystore::create(&path, "master psw");
+    keystore.set_tree_password(state_psw);
+    keystore.set_daily_password("psw2");
+    keystore.save()?;
+
+    let keystore: Arc<dyn Keystore> = Arc::new(FileKeystore::open(&path, "master psw")?);
+    let root = RootChannel::import_from_tangle_with_keystore(info.channel_id(), info.announce_id(), keystore, transport).await?;
+    root.get_daily_channel(Category::scales(), "SCALE1", date(24, 9, 2021)).await?;
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+async fn test_daily_channel_for(info: ChannelInfo, state_psw: &str, transport: Arc<dyn Transport>, key_nonce: Option<([u8; 32],[u8; 24])>) -> anyhow::Result<()>{
+    let root = RootChannel::import_from_tangle_with_transport(info.channel_id(), info.announce_id(), state_psw, transport).await?;
+    let now = current_time_secs();
+    let today = root.daily_actor_channel_for(Category::trucks(), "XASD4", "psw2", now).await?;
+    let again = root.daily_actor_channel_for(Category::trucks(), "XASD4", "psw2", now).await?;
+    assert_eq!(today.channel_info().to_string(), again.channel_info().to_string());
+
+    // Sends after midnight go to the channel of the new day
+    let mut rolling = RollingDailyChannel::new(Arc::new(root), Category::trucks(), "XASD4", "psw2").await?;
+    rolling.send(&Message::new("PUBLIC MESSAGE"), &Message::new("PRIVATE MESSAGE"), key_nonce).await?;
+    let tomorrow = rolling.channel_for(now + 24 * 3600).await?;
+    println!("Daily channels in {}: {} = {}, {} = {}", today.timezone(), today.creation_date()?, today.channel_info().to_string(),
+             tomorrow.creation_date()?, tomorrow.channel_info().to_string());
+    Ok(())
+}
+
+async fn test_rotate_password(info: ChannelInfo, state_psw: &str, new_psw: &str, transport: Arc<dyn Transport>) -> anyhow::Result<()>{
+    let mut root = RootChannel::import_from_tangle_with_transport(info.channel_id(), info.announce_id(), state_psw, transport.clone()).await?;
+    let state = root.rotate_daily_channel_password(Category::trucks(), "XASD", "psw2", new_psw, date(24, 9, 2021)).await?;
+    DailyChannelManager::import_from_base64_with_transport(&state, new_psw, transport.clone()).await?;
+    let backup = root.rotate_password(state_psw, new_psw).await?;
+    let root = RootChannel::restore_backup_with_transport(&backup.bytes, new_psw, transport, false).await?;
+    root.print_nested_channel_info().await;
     Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()>{
+    tracing_subscriber::fmt::init();
     let key = create_encryption_key("This is a secret key");
     let nonce = create_encryption_nonce("This is a secret nonce");
     let key_nonce = Some((key, nonce));
     let state_psw = "psw";
-    let mainnet = false;
-    let info = test_create_nested_channels(state_psw, mainnet, key_nonce).await?;
-    test_restore_nested_channels(info, state_psw, mainnet, key_nonce).await?;
+    let network = NetworkConfig::devnet();
+    // Run with `--offline` to use a local in-memory tangle instead of a remote node
+    let offline = std::env::args().any(|arg| arg == "--offline");
+    let transport: Arc<dyn Transport> = if offline{
+        Arc::new(InMemoryTransport::with_network(network))
+    }else{
+        Arc::new(TangleTransport::new(network))
+    };
+    let info = test_create_nested_channels(state_psw, transport.clone(), key_nonce).await?;
+    test_restore_nested_channels(info.clone(), state_psw, transport.clone(), key_nonce).await?;
+    test_subscription(info.clone(), state_psw, transport.clone(), key_nonce).await?;
+    test_backup_and_restore(info.clone(), state_psw, transport.clone()).await?;
+    test_keystore(info.clone(), state_psw, transport.clone()).await?;
+    test_daily_channel_for(info.clone(), state_psw, transport.clone(), key_nonce).await?;
+    test_rotate_password(info, state_psw, "new psw", transport).await?;
     Ok(())
 }
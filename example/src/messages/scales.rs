@@ -1,35 +1,6 @@
-use serde::{Serialize, Deserialize};
-use crate::messages::ChannelReference;
+use bioenpro4to_channel_manager::messages::{ScaleMessage, TruckInfo, ChannelReference};
 use bioenpro4to_channel_manager::utils::current_time_secs;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ScaleMessage{
-    plant: String,
-    timestamp: i64,
-    truck_info: TruckInfo
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct TruckInfo{
-    weight: f32,
-    driver: String,
-    license_plate: String,
-    msg_info: ChannelReference
-}
-
-impl TruckInfo{
-    pub fn new(weight: f32, driver: String, license_plate: String, msg_info: ChannelReference) -> Self {
-        TruckInfo { weight, driver, license_plate, msg_info }
-    }
-}
-
-impl ScaleMessage{
-    pub fn new(plant: String, timestamp: i64, truck_info: TruckInfo) -> Self {
-        ScaleMessage { plant, timestamp, truck_info }
-    }
-}
-
-
 pub struct ScaleMessageGenerator{
     channel_id: String,
     announce_id: String,
@@ -50,7 +21,7 @@ impl ScaleMessageGenerator{
         }
     }
 
-    pub fn next(&mut self) -> ScaleMessage{
+    pub fn next(&mut self) -> anyhow::Result<ScaleMessage>{
         let truck_info = TruckInfo::new(
             320.0,
             self.driver.clone(),
@@ -60,13 +31,13 @@ impl ScaleMessageGenerator{
                 self.msg_id.clone()
             )
         );
-        let message = ScaleMessage::new(
-            self.plant.clone(),
-            self.timestamp,
-            truck_info
-        );
+        let message = ScaleMessage::builder()
+            .plant(&self.plant)
+            .timestamp(self.timestamp)
+            .truck_info(truck_info)
+            .build()?;
 
         self.timestamp += 3600;
-        message
+        Ok(message)
     }
 }
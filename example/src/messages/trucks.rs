@@ -1,37 +1,6 @@
-use serde::{Serialize, Deserialize};
+use bioenpro4to_channel_manager::messages::{TruckMessage, TruckStepMessage};
 use bioenpro4to_channel_manager::utils::current_time_secs;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TruckMessage{
-    license_plate: String,
-    driver: String,
-    timestamp: i64,
-    max_weight: f32,
-    last_update: TruckStepMessage
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct TruckStepMessage{
-    weight: f32,
-    total_weight: f32,
-    latitude: u32,
-    longitude: u32,
-    fuel: f32,
-}
-
-impl TruckStepMessage{
-    pub fn new(weight: f32, total_weight: f32, latitude: u32, longitude: u32, fuel: f32) -> Self {
-        TruckStepMessage { weight, total_weight, latitude, longitude, fuel }
-    }
-}
-
-impl TruckMessage{
-    pub fn new(license_plate: String, driver: String, timestamp: i64, max_weight: f32, last_update: TruckStepMessage) -> Self {
-        TruckMessage { license_plate, driver, timestamp, max_weight, last_update }
-    }
-}
-
-
 pub struct TruckMessageGenerator{
     license_plate: String,
     driver: String,
@@ -56,17 +25,19 @@ impl TruckMessageGenerator{
         }
     }
 
-    pub fn next(&mut self) -> TruckMessage{
+    pub fn next(&mut self) -> anyhow::Result<TruckMessage>{
         let step = TruckStepMessage::new(
             12.5, self.cumulative_weight,
             100, 100,
             self.fuel
         );
-        let message = TruckMessage::new(
-            self.license_plate.clone(), self.driver.clone(),
-            self.timestamp, self.max_weight,
-            step
-        );
+        let message = TruckMessage::builder()
+            .license_plate(&self.license_plate)
+            .driver(&self.driver)
+            .timestamp(self.timestamp)
+            .max_weight(self.max_weight)
+            .last_update(step)
+            .build()?;
 
         self.timestamp += (1.5*3600.0) as i64;
         self.cumulative_weight += 12.5;
@@ -74,6 +45,6 @@ impl TruckMessageGenerator{
         self.latitude += 5;
         self.longitude += 5;
 
-        message
+        Ok(message)
     }
 }
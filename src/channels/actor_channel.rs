@@ -1,25 +1,39 @@
-use crate::channels::daily_channel::DailyChannel;
-use crate::channels::{Category, create_channel, ChannelInfo, create_reader, node_url};
-use crate::utils::{current_time_secs, timestamp_to_date, timestamp_to_date_string, hash_string};
-use chrono::Datelike;
-use iota_streams_lib::payload::payload_serializers::{JsonPacketBuilder, JsonPacket};
+use crate::channels::daily_channel::{self, DailyChannel};
+use crate::channels::{Category, ChannelInfo, NetworkConfig};
+use crate::channels::errors::{self, ChannelManagerError, ChannelLayer};
+use crate::channels::cache::{DailyChannelCache, CacheKey};
+use crate::channels::backup::{ActorBackup, DailyChannelBackup, RotatedPasswordBackup, WriterBackup};
+use crate::channels::encoding::{self, PayloadEncoding};
+use crate::utils::{timestamp_to_day, timestamp_to_day_string, date_to_string, hash_string, utc, Tz};
+use crate::transport::{StreamsWriter, Transport, send_public_packet, fetch_public_packets};
+use crate::messages::CategoryMessage;
+use chrono::NaiveDate;
 use serde::{Serialize, Deserialize};
-use iota_streams_lib::channels::ChannelWriter;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::debug;
+use zeroize::Zeroizing;
 
+//
+// The creation timestamp is the instant the day of the channel starts in the timezone of the tree,
+// that is not published with every channel but set when the messages are read
+//
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DailyChannelMsg{
     address: ChannelInfo,
     category: String,
     actor_id: String,
     creation_timestamp: i64,
+    #[serde(skip, default = "utc")]
+    timezone: Tz,
 }
 
 #[allow(dead_code)]
 impl DailyChannelMsg{
-    pub fn new(address: ChannelInfo, category: Category, actor_id: &str, creation_timestamp: i64) -> Self {
-        DailyChannelMsg { address, category: category.to_string(), actor_id: actor_id.to_lowercase(), creation_timestamp }
+    pub fn new(address: ChannelInfo, category: Category, actor_id: &str, creation_timestamp: i64, timezone: Tz) -> Self {
+        DailyChannelMsg { address, category: category.to_string(), actor_id: actor_id.to_lowercase(), creation_timestamp, timezone }
     }
     pub fn address(&self) -> &ChannelInfo {
         &self.address
@@ -33,37 +47,86 @@ impl DailyChannelMsg{
     pub fn creation_timestamp(&self) -> i64 {
         self.creation_timestamp
     }
-    pub fn creation_date(&self) -> String{
-        timestamp_to_date_string(self.creation_timestamp, false)
+    pub fn timezone(&self) -> Tz {
+        self.timezone
     }
-    pub (crate) fn print_nested_channel_info(&self, mainnet: bool){
-        if self.category == String::from("biocells"){
-            println!("        |--Day {} = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", self.creation_date(), self.address.channel_id, self.address.announce_id, mainnet);
-        }else{
-            println!("|   |   |--Day {} = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", self.creation_date(), self.address.channel_id, self.address.announce_id, mainnet);
-        }
+    pub fn creation_day(&self) -> errors::Result<NaiveDate> {
+        timestamp_to_day(self.creation_timestamp, &self.timezone).ok_or(ChannelManagerError::InvalidDate)
+    }
+    pub fn creation_date(&self) -> errors::Result<String>{
+        timestamp_to_day_string(self.creation_timestamp, &self.timezone).ok_or(ChannelManagerError::InvalidDate)
+    }
+    pub (crate) fn in_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
     }
+    pub (crate) fn print_nested_channel_info(&self, network: &NetworkConfig, prefix: &str){
+        let date = self.creation_date().unwrap_or_else(|_| self.creation_timestamp.to_string());
+        println!("{}|   |--Day {} = {}", prefix, date, self.address.explorer_url(network));
+    }
+}
+
+impl DailyChannelMsg{
+    //
+    // Backup of a daily channel that is not in the cache, its writer is imported from the tangle with the password it was opened with.
+    // The channel is restored in the cache under the password of the channel
+    //
+    pub (crate) async fn backup(&self, category: &Category, state_psw: &str, tangle_psw: &str, backup_psw: &str,
+                                transport: Arc<dyn Transport>) -> errors::Result<DailyChannelBackup>{
+        let daily_channel = DailyChannel::import_from_tangle(
+            self.address.channel_id(), self.address.announce_id(),
+            tangle_psw, category.clone(),
+            &self.actor_id,
+            self.creation_timestamp,
+            self.timezone,
+            transport
+        ).await?;
+        let key = CacheKey::new(category, &self.actor_id, self.creation_day()?, state_psw);
+        DailyChannelManager::new(daily_channel).backup(&key, backup_psw).await
+    }
+}
+
+//
+// Password of a daily channel rotated after the channel has been opened: the tree accepts only the new one,
+// the writer on the tangle is still imported with the password it was opened with
+//
+struct RotatedPassword{
+    psw_hash: String,
+    tangle_psw: Zeroizing<String>,
 }
 
 pub (crate) struct ActorChannel{
     category: Category,
     actor_id: String,
-    channel: ChannelWriter,
-    daily_channels: Vec<DailyChannelMsg>,
-    imported_channels: HashMap<(String, String), Arc<Mutex<DailyChannel>>>,
-    mainnet: bool,
+    channel: Box<dyn StreamsWriter>,
+    daily_channels: BTreeMap<NaiveDate, DailyChannelMsg>,
+    rotated_passwords: BTreeMap<NaiveDate, RotatedPassword>,
+    timezone: Tz,
+    imported_channels: Arc<DailyChannelCache>,
+    transport: Arc<dyn Transport>,
 }
 
 impl ActorChannel{
-    pub (crate) fn new(category: Category, actor_id: &str, mainnet: bool) -> Self {
-        let channel = create_channel(mainnet);
-        ActorChannel { category, actor_id: actor_id.to_lowercase(), channel, daily_channels: vec![], imported_channels: HashMap::new(), mainnet }
+    pub (crate) fn new(category: Category, actor_id: &str, timezone: Tz, transport: Arc<dyn Transport>, imported_channels: Arc<DailyChannelCache>) -> Self {
+        let channel = transport.create_writer();
+        ActorChannel {
+            category,
+            actor_id: actor_id.to_lowercase(),
+            channel,
+            daily_channels: BTreeMap::new(),
+            rotated_passwords: BTreeMap::new(),
+            timezone,
+            imported_channels,
+            transport
+        }
     }
 
-    pub (crate) async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, category: Category, actor_id: &str, mainnet: bool) -> anyhow::Result<Self>{
-        let node_url = node_url(mainnet);
-        let channel = ChannelWriter::import_from_tangle(channel_id, announce_id, state_psw, Some(node_url.as_str()), None).await?;
-        let daily_channels = ActorChannel::read_daily_channels_info(channel_id, announce_id, mainnet).await?;
+    #[allow(clippy::too_many_arguments)]
+    pub (crate) async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, category: Category, actor_id: &str, timezone: Tz,
+                                            transport: Arc<dyn Transport>, imported_channels: Arc<DailyChannelCache>) -> errors::Result<Self>{
+        let channel = transport.import_from_tangle(channel_id, announce_id, state_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Actor, format!("{}:{}", channel_id, announce_id), err))?;
+        let daily_channels = index_by_day(ActorChannel::read_daily_channels_info(channel_id, announce_id, timezone, transport.as_ref()).await?)?;
         /*let mut daily_channels = vec![];
         for d in daily_info {
             let ch = DailyChannel::import_from_tangle(
@@ -73,55 +136,112 @@ impl ActorChannel{
                 category.clone(),
                 actor_id,
                 d.creation_timestamp(),
-                mainnet
+                transport.clone()
             ).await?;
             daily_channels.push(Rc::new(RefCell::new(ch)));
         }*/
-        Ok( ActorChannel{category, actor_id: actor_id.to_lowercase(), channel, daily_channels, imported_channels: HashMap::new(), mainnet } )
+        Ok( ActorChannel{
+            category,
+            actor_id: actor_id.to_lowercase(),
+            channel,
+            daily_channels,
+            rotated_passwords: BTreeMap::new(),
+            timezone,
+            imported_channels,
+            transport
+        } )
     }
 
-    pub (crate) async fn open(&mut self, channel_psw: &str) -> anyhow::Result<ChannelInfo> {
+    pub (crate) async fn open(&mut self, channel_psw: &str) -> errors::Result<ChannelInfo> {
         let info = self.channel.open_and_save(channel_psw).await?;
         Ok(ChannelInfo::new(info.0, info.1))
     }
 
+    pub (crate) fn backup(&self, channel_psw: &str) -> errors::Result<ActorBackup>{
+        let writer = WriterBackup{ address: self.channel_info(), state: self.channel.export_to_bytes(channel_psw)? };
+        let rotated_passwords = self.rotated_passwords.iter()
+            .filter_map(|(date, rotated)| self.daily_channels.get(date).map(|msg| RotatedPasswordBackup{
+                creation_timestamp: msg.creation_timestamp(),
+                psw_hash: rotated.psw_hash.clone(),
+                tangle_psw: rotated.tangle_psw.to_string(),
+            }))
+            .collect();
+        Ok(ActorBackup{ actor_id: self.actor_id.clone(), writer, daily_channels: self.daily_channels_info(), rotated_passwords })
+    }
+
+    pub (crate) async fn restore(backup: &ActorBackup, channel_psw: &str, category: Category, timezone: Tz,
+                                 transport: Arc<dyn Transport>, imported_channels: Arc<DailyChannelCache>) -> errors::Result<Self>{
+        let channel = transport.import_from_bytes(&backup.writer.state, channel_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Actor, backup.writer.address.to_string(), err))?;
+        let rotated_passwords = backup.rotated_passwords.iter()
+            .map(|rotated| {
+                let date = timestamp_to_day(rotated.creation_timestamp, &timezone).ok_or(ChannelManagerError::InvalidDate)?;
+                Ok((date, RotatedPassword{ psw_hash: rotated.psw_hash.clone(), tangle_psw: Zeroizing::new(rotated.tangle_psw.clone()) }))
+            })
+            .collect::<errors::Result<BTreeMap<_, _>>>()?;
+        Ok( ActorChannel{
+            category,
+            actor_id: backup.actor_id.clone(),
+            channel,
+            daily_channels: index_by_day(backup.daily_channels.iter().map(|d| d.clone().in_timezone(timezone)))?,
+            rotated_passwords,
+            timezone,
+            imported_channels,
+            transport
+        } )
+    }
+
+    //
+    // Add the daily channels announced on the tangle that are not known yet, returns how many have been added
+    //
+    pub (crate) async fn resync(&mut self) -> errors::Result<usize>{
+        let info = self.channel_info();
+        let announced = ActorChannel::read_daily_channels_info(info.channel_id(), info.announce_id(), self.timezone, self.transport.as_ref()).await?;
+        let mut added = 0;
+        for msg in announced {
+            if let Entry::Vacant(entry) = self.daily_channels.entry(msg.creation_day()?){
+                entry.insert(msg);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
 
-    pub (crate) async fn new_daily_actor_channel(&mut self, state_psw: &str, day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
-        // Cerco se la data è presente all'interno dei daily channel msgs
-        let date_string = format!("{:02}/{:02}/{}", day, month, year);
-        let daily_ch_msg = self.daily_channels.iter()
-            .find(|ch| { date_string == ch.creation_date() });
 
-        match daily_ch_msg {
+    pub (crate) async fn new_daily_actor_channel(&mut self, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        // Cerco se la data è presente all'interno dei daily channel msgs
+        match self.daily_channels.get(&date) {
             None => { // Se non è stata trovata la data corrispondente allora viene creato un nuovo channel
                 let mut daily_channel = DailyChannel::new_in_date(
-                    self.category.clone(), self.actor_id(), day, month, year, self.mainnet
+                    self.category.clone(), self.actor_id(), date, self.timezone, self.transport.clone()
                 )?;
                 let timestamp = daily_channel.creation_timestamp();
                 let info = daily_channel.open(state_psw).await?;
                 let daily_ch_msg = self.publish_daily_channel(info, timestamp).await?;
-                self.daily_channels.push(daily_ch_msg);
-                let cell = Arc::new(Mutex::new(daily_channel));
-                self.imported_channels.insert((date_string.clone(), hash_string(state_psw)), cell.clone());
-                Ok(DailyChannelManager::new(cell))
+                self.daily_channels.insert(date, daily_ch_msg);
+                let manager = DailyChannelManager::new(daily_channel);
+                self.imported_channels.insert(self.cache_key(date, state_psw), manager.clone()).await;
+                Ok(manager)
             },
             // Altrimenti si ritorna errore channel gia creato
-            Some(_) => Err(anyhow::Error::msg(format!("Daily channel in date {} already exist", date_string)))
+            Some(_) => Err(ChannelManagerError::DailyChannelExists(date_to_string(&date)))
         }
     }
 
-    pub (crate) async fn get_daily_channel_in_date(&mut self, state_psw: &str, day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
+    pub (crate) async fn get_daily_channel_in_date(&mut self, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
         // Cerco se la data è presente all'interno dei daily channel msgs
-        let date_string = format!("{:02}/{:02}/{}", day, month, year);
-        let daily_ch_msg = self.daily_channels.iter()
-            .find(|ch| { date_string == ch.creation_date() });
-
-        let (ch, daily_ch_msg) = match daily_ch_msg {
+        let (ch, daily_ch_msg) = match self.daily_channels.get(&date) {
             Some(info) => { // Se esiste si ricerca agli interno degli imported
-                ( self.imported_channels.get(&(date_string.clone(), hash_string(state_psw))), info )
+                ( self.imported_channels.get(&self.cache_key(date, state_psw)).await, info )
             }
             // Altrimenti viene ritornato errore
-            None => return Err(anyhow::Error::msg(format!("Daily Channel date or psw wrong"))),
+            None => return Err(ChannelManagerError::DailyChannelNotFound(date_to_string(&date))),
+        };
+        // Se la password è stata cambiata si accetta solo la nuova, il writer sul tangle ha ancora quella originale
+        let tangle_psw = match self.rotated_passwords.get(&date){
+            Some(rotated) if rotated.psw_hash != hash_string(state_psw) => return Err(ChannelManagerError::WrongPassword),
+            Some(rotated) => rotated.tangle_psw.clone(),
+            None => Zeroizing::new(state_psw.to_string()),
         };
 
         let res = match ch{
@@ -130,34 +250,60 @@ impl ActorChannel{
                 let announce_id = daily_ch_msg.address.announce_id();
                 DailyChannel::import_from_tangle(
                     channel_id, announce_id,
-                    state_psw, self.category.clone(),
+                    &tangle_psw, self.category.clone(),
                     self.actor_id(),
                     daily_ch_msg.creation_timestamp(),
-                    self.mainnet
+                    self.timezone,
+                    self.transport.clone()
                 ).await
             },
-            Some(ch) => return Ok(DailyChannelManager::new(ch.clone())) // Altrimenti si ritorna direttamente
+            Some(ch) => { // Altrimenti si ritorna direttamente
+                debug!(actor_id = %self.actor_id, date = %date_to_string(&date), "daily channel found among the imported ones");
+                return Ok(ch)
+            }
         };
 
         match res{
             Ok(res) => {
-                let cell = Arc::new(Mutex::new(res));
-                self.imported_channels.insert((date_string.clone(), hash_string(state_psw)),cell.clone());
-                Ok(DailyChannelManager::new(cell))
-            } // Se c'è stato un errore durante il restore dal tangle probabilmente la password inserita sarà sbagliata
-            Err(_) => Err(anyhow::Error::msg(format!("Impossible to get the channel in date {} because password is wrong", date_string)))
+                debug!(actor_id = %self.actor_id, date = %date_to_string(&date), "daily channel restored from the tangle");
+                let manager = DailyChannelManager::new(res);
+                self.imported_channels.insert(self.cache_key(date, state_psw), manager.clone()).await;
+                Ok(manager)
+            }
+            Err(err) => Err(err)
+        }
+    }
+
+    //
+    // Channel of the day, created if it doesn't exist yet
+    //
+    pub (crate) async fn daily_channel_for(&mut self, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        if self.daily_channels.contains_key(&date){
+            self.get_daily_channel_in_date(state_psw, date).await
+        } else {
+            self.new_daily_actor_channel(state_psw, date).await
         }
     }
 
-    #[allow(dead_code)]
-    pub (crate) async fn get_daily_channel(&mut self, state_psw: &str) -> anyhow::Result<DailyChannelManager>{
-        let date = timestamp_to_date(current_time_secs(), false);
-        self.get_daily_channel_in_date(state_psw, date.day() as u16, date.month() as u16, date.year() as u16).await
+    //
+    // The state of the channel is exported with the new password and the channel is moved in the cache under it,
+    // from now on the old password is refused. The writer on the tangle keeps the password it was opened with
+    //
+    pub (crate) async fn rotate_daily_channel_password(&mut self, old_psw: &str, new_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        let daily_ch = self.get_daily_channel_in_date(old_psw, date).await?;
+        let state = daily_ch.export_to_base64(new_psw).await?;
+        let tangle_psw = match self.rotated_passwords.remove(&date){
+            Some(rotated) => rotated.tangle_psw,
+            None => Zeroizing::new(old_psw.to_string()),
+        };
+        self.rotated_passwords.insert(date, RotatedPassword{ psw_hash: hash_string(new_psw), tangle_psw });
+        self.imported_channels.rekey(&self.cache_key(date, old_psw), self.cache_key(date, new_psw)).await;
+        Ok(state)
     }
 
-    pub (crate) async fn serialize_daily_channel(&mut self, state_psw: &str, day: u16, month: u16, year: u16) -> anyhow::Result<String>{
-        let daily_ch = self.get_daily_channel_in_date(state_psw, day, month, year).await?;
-        daily_ch.export_to_base64(state_psw)
+    pub (crate) async fn serialize_daily_channel(&mut self, state_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        let daily_ch = self.get_daily_channel_in_date(state_psw, date).await?;
+        daily_ch.export_to_base64(state_psw).await
     }
 
     pub (crate) fn actor_id(&self) -> &str {
@@ -169,78 +315,206 @@ impl ActorChannel{
         ChannelInfo::new(info.0, info.1)
     }
 
-    pub (crate) fn print_nested_channel_info(&self){
+    pub (crate) fn print_nested_channel_info(&self, prefix: &str){
         let info = self.channel_info();
-        if self.category.is_biocells(){
-            println!("    |--Actor {} = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", self.actor_id, info.channel_id, info.announce_id, self.mainnet);
-        }else{
-            println!("|   |--Actor {} = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", self.actor_id, info.channel_id, info.announce_id, self.mainnet);
-        }
+        let network = self.transport.network();
+        println!("{}|--Actor {} = {}", prefix, self.actor_id, info.explorer_url(network));
 
-        self.daily_channels.iter().for_each(|ch| ch.print_nested_channel_info(self.mainnet));
+        self.daily_channels.values().for_each(|ch| ch.print_nested_channel_info(network, prefix));
     }
 }
 
 impl ActorChannel{
-    async fn publish_daily_channel(&mut self, info: ChannelInfo, timestamp: i64) -> anyhow::Result<DailyChannelMsg>{
-        let msg = DailyChannelMsg::new(info, self.category.clone(), self.actor_id(), timestamp);
-        let packet = JsonPacketBuilder::new()
-            .public(&msg)?
-            .build();
-        self.channel.send_signed_packet(&packet).await?;
+    fn cache_key(&self, date: NaiveDate, state_psw: &str) -> CacheKey{
+        CacheKey::new(&self.category, &self.actor_id, date, state_psw)
+    }
+
+    async fn publish_daily_channel(&mut self, info: ChannelInfo, timestamp: i64) -> errors::Result<DailyChannelMsg>{
+        let msg = DailyChannelMsg::new(info, self.category.clone(), self.actor_id(), timestamp, self.timezone);
+        send_public_packet(self.channel.as_mut(), &msg).await?;
         Ok(msg)
     }
 
-    async fn read_daily_channels_info(channel_id: &str, announce_id: &str, mainnet: bool) -> anyhow::Result<Vec<DailyChannelMsg>>{
-        let mut reader = create_reader(channel_id, announce_id, mainnet);
+    async fn read_daily_channels_info(channel_id: &str, announce_id: &str, timezone: Tz, transport: &dyn Transport) -> errors::Result<Vec<DailyChannelMsg>>{
+        let mut reader = transport.create_reader(channel_id, announce_id);
         reader.attach().await?;
-        let msgs: Vec<(String, JsonPacket)> = reader.fetch_parsed_msgs(&None).await?;
-        let mut daily_ch_info = vec![];
-        for (_, m) in msgs {
-            daily_ch_info.push(m.deserialize_public()?)
-        }
+        let msgs = fetch_public_packets(reader.as_mut()).await?;
+        let daily_ch_info = msgs.into_iter().map(|(_, m): (_, DailyChannelMsg)| m.in_timezone(timezone)).collect();
         Ok(daily_ch_info)
     }
 }
 
+//
+// Handle of a single daily channel. Every daily channel has its own lock, so sends on different
+// daily channels never wait on each other; the immutable info is kept outside the lock
+//
 #[derive(Clone)]
 pub struct DailyChannelManager{
     daily_channel: Arc<Mutex<DailyChannel>>,
+    info: ChannelInfo,
+    category: Category,
+    creation_timestamp: i64,
+    timezone: Tz,
+    encoding: PayloadEncoding,
 }
 
 impl DailyChannelManager {
-    fn new(daily_channel: Arc<Mutex<DailyChannel>>) -> Self {
-        DailyChannelManager { daily_channel }
+    fn new(daily_channel: DailyChannel) -> Self {
+        let info = daily_channel.channel_info();
+        let category = daily_channel.category().clone();
+        let creation_timestamp = daily_channel.creation_timestamp();
+        let timezone = daily_channel.timezone();
+        DailyChannelManager {
+            daily_channel: Arc::new(Mutex::new(daily_channel)),
+            info,
+            category,
+            creation_timestamp,
+            timezone,
+            encoding: PayloadEncoding::default()
+        }
     }
 
-    pub async fn import_from_base64(state: &str, state_psw: &str) -> anyhow::Result<Self>{
-        let daily_ch = DailyChannel::import_from_base64(state, state_psw).await?;
-        Ok(DailyChannelManager::new(Arc::new(Mutex::new(daily_ch))))
+    pub (crate) async fn backup(&self, key: &CacheKey, psw: &str) -> errors::Result<DailyChannelBackup>{
+        let daily_channel = self.daily_channel.lock().await;
+        Ok(DailyChannelBackup{
+            category: self.category.clone(),
+            actor_id: key.actor_id.clone(),
+            creation_timestamp: self.creation_timestamp,
+            psw_hash: key.psw_hash.clone(),
+            writer: WriterBackup{ address: self.info.clone(), state: daily_channel.export_writer(psw)? },
+        })
     }
 
-    fn export_to_base64(&self, state_psw: &str) -> anyhow::Result<String>{
-        self.daily_channel.lock().unwrap().export_to_base64(state_psw)
+    pub (crate) async fn restore(backup: &DailyChannelBackup, psw: &str, timezone: Tz, transport: Arc<dyn Transport>) -> errors::Result<(CacheKey, Self)>{
+        let daily_channel = DailyChannel::restore(backup, psw, timezone, transport).await?;
+        let key = CacheKey{
+            category: backup.category.id().to_string(),
+            actor_id: backup.actor_id.clone(),
+            date: timestamp_to_day(backup.creation_timestamp, &timezone).ok_or(ChannelManagerError::InvalidDate)?,
+            psw_hash: backup.psw_hash.clone(),
+        };
+        Ok((key, DailyChannelManager::new(daily_channel)))
+    }
+
+    //
+    // The cache holds one of the references, any other one is a handle returned to a caller
+    //
+    pub (crate) fn in_use(&self) -> bool {
+        Arc::strong_count(&self.daily_channel) > 1
+    }
+
+    pub async fn import_from_base64(state: &str, state_psw: &str) -> errors::Result<Self>{
+        let daily_ch = DailyChannel::import_from_base64(state, state_psw, None).await?;
+        Ok(DailyChannelManager::new(daily_ch))
+    }
+
+    pub async fn import_from_base64_with_transport(state: &str, state_psw: &str, transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        let daily_ch = DailyChannel::import_from_base64(state, state_psw, Some(transport)).await?;
+        Ok(DailyChannelManager::new(daily_ch))
+    }
+
+    //
+    // Upgrade a state exported by an older version of the library to the current format
+    //
+    pub fn upgrade_state(state: &str, state_psw: &str) -> errors::Result<String>{
+        daily_channel::upgrade_state(state, state_psw)
     }
 
-    pub async fn send_raw_packet(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> anyhow::Result<String>{
-        self.daily_channel.lock().unwrap().send_raw_packet(p_data, m_data, key_nonce).await
+    async fn export_to_base64(&self, state_psw: &str) -> errors::Result<String>{
+        self.daily_channel.lock().await.export_to_base64(state_psw)
+    }
+
+    //
+    // Export the state of the daily channel with a new password, the old one is checked against the channel on the tangle.
+    // The channel on the tangle keeps the password it was opened with, that is still needed to import it from there.
+    // The states exported before keep working with the old password
+    //
+    pub async fn rotate_password(&mut self, old_psw: &str, new_psw: &str) -> errors::Result<String>{
+        self.daily_channel.lock().await.export_with_new_password(old_psw, new_psw).await
+    }
+
+    pub async fn send_raw_packet(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        self.daily_channel.lock().await.send_raw_packet(p_data, m_data, key_nonce).await
+    }
+
+    //
+    // Encoding used by send, every handle keeps its own
+    //
+    pub fn with_encoding(mut self, encoding: PayloadEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn set_encoding(&mut self, encoding: PayloadEncoding){
+        self.encoding = encoding;
+    }
+
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    //
+    // Send a packet encoding the payloads with the encoding of the handle, that is recorded in the public payload
+    //
+    pub async fn send<P: Serialize, M: Serialize>(&mut self, public: &P, masked: &M, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        let p_data = encoding::encode_public(self.encoding, public)?;
+        let m_data = self.encoding.encode(masked)?;
+        self.send_raw_packet(p_data, m_data, key_nonce).await
+    }
+
+    //
+    // Send a message of the schema of the channel category, after validating it
+    //
+    pub async fn send_typed<P: CategoryMessage, M: Serialize>(&mut self, public: &P, masked: &M, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        if P::category() != self.category{
+            return Err(ChannelManagerError::SchemaMismatch{ expected: self.category.to_string(), found: P::category().to_string() });
+        }
+        public.validate()?;
+        self.send(public, masked, key_nonce).await
     }
 
     pub fn creation_timestamp(&self) -> i64 {
-        self.daily_channel.lock().unwrap().creation_timestamp()
+        self.creation_timestamp
+    }
+
+    //
+    // Day of the channel in the timezone of the plant
+    //
+    pub fn creation_day(&self) -> errors::Result<NaiveDate> {
+        timestamp_to_day(self.creation_timestamp, &self.timezone).ok_or(ChannelManagerError::InvalidDate)
     }
 
-    pub fn creation_date(&self) -> String{
-        self.daily_channel.lock().unwrap().creation_date()
+    pub fn creation_date(&self) -> errors::Result<String>{
+        timestamp_to_day_string(self.creation_timestamp, &self.timezone).ok_or(ChannelManagerError::InvalidDate)
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub fn category(&self) -> &Category {
+        &self.category
     }
 
     pub fn channel_info(&self) -> ChannelInfo{
-        self.daily_channel.lock().unwrap().channel_info()
+        self.info.clone()
     }
 }
 
 impl ActorChannel{
     pub fn daily_channels_info(&self) -> Vec<DailyChannelMsg>{
-        self.daily_channels.clone()
+        self.daily_channels.values().cloned().collect()
+    }
+}
+
+//
+// Daily channels by the day they belong to, if a day has been announced twice the first channel is kept.
+// An announcement with a timestamp out of the range of the dates fails the whole index
+//
+fn index_by_day(msgs: impl IntoIterator<Item = DailyChannelMsg>) -> errors::Result<BTreeMap<NaiveDate, DailyChannelMsg>>{
+    let mut index = BTreeMap::new();
+    for msg in msgs {
+        index.entry(msg.creation_day()?).or_insert(msg);
     }
+    Ok(index)
 }
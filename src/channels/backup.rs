@@ -0,0 +1,106 @@
+use crate::channels::{Category, ChannelInfo};
+use crate::channels::category_channel::ActorChannelMsg;
+use crate::channels::actor_channel::DailyChannelMsg;
+use crate::utils::Tz;
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroize;
+
+//
+// Content of the encrypted backup of a whole tree.
+// Every writer is stored as its exported state next to the messages it has announced,
+// so that the tree can be rebuilt without reading anything from the tangle.
+// The password of the writers on the tangle is kept as well, it differs from the one of the backup after a rotation.
+// Daily channel writers are taken from the cache of the tree, the others are imported from the tangle
+// with the passwords of the keystore of the tree
+//
+pub (crate) const BACKUP_MAGIC: [u8; 4] = *b"B4TB";
+pub const BACKUP_FORMAT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub (crate) struct WriterBackup{
+    pub (crate) address: ChannelInfo,
+    pub (crate) state: Vec<u8>,
+}
+
+impl Drop for WriterBackup{
+    fn drop(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub (crate) struct ActorBackup{
+    pub (crate) actor_id: String,
+    pub (crate) writer: WriterBackup,
+    pub (crate) daily_channels: Vec<DailyChannelMsg>,
+    pub (crate) rotated_passwords: Vec<RotatedPasswordBackup>,
+}
+
+//
+// Daily channel whose password has been rotated: the hash of the password accepted by the tree
+// and the password of the writer on the tangle
+//
+#[derive(Serialize, Deserialize)]
+pub (crate) struct RotatedPasswordBackup{
+    pub (crate) creation_timestamp: i64,
+    pub (crate) psw_hash: String,
+    pub (crate) tangle_psw: String,
+}
+
+impl Drop for RotatedPasswordBackup{
+    fn drop(&mut self) {
+        self.tangle_psw.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub (crate) struct CategoryBackup{
+    pub (crate) category: Category,
+    pub (crate) writer: WriterBackup,
+    pub (crate) actors: Vec<ActorChannelMsg>,
+    pub (crate) actor_channels: Vec<ActorBackup>,
+}
+
+//
+// The writer state is exported with the password of the backup, the hash of the password of the channel
+// is kept to put it back in the cache under the same key
+//
+#[derive(Serialize, Deserialize)]
+pub (crate) struct DailyChannelBackup{
+    pub (crate) category: Category,
+    pub (crate) actor_id: String,
+    pub (crate) creation_timestamp: i64,
+    pub (crate) psw_hash: String,
+    pub (crate) writer: WriterBackup,
+}
+
+#[derive(Serialize, Deserialize)]
+pub (crate) struct TreeBackup{
+    pub (crate) root: WriterBackup,
+    pub (crate) categories: Vec<CategoryBackup>,
+    pub (crate) created_at: i64,
+    pub (crate) timezone: Tz,
+    pub (crate) daily_channels: Vec<DailyChannelBackup>,
+    pub (crate) tangle_psw: String,
+}
+
+impl Drop for TreeBackup{
+    fn drop(&mut self) {
+        self.tangle_psw.zeroize();
+    }
+}
+
+//
+// Encrypted backup returned by the export. The daily channels that are not in the cache and whose password
+// is not given by a keystore can't be saved, they are listed in `missing_daily_channels`
+//
+pub struct ExportedBackup{
+    pub bytes: Vec<u8>,
+    pub missing_daily_channels: Vec<DailyChannelMsg>,
+}
+
+impl ExportedBackup{
+    pub fn is_complete(&self) -> bool {
+        self.missing_daily_channels.is_empty()
+    }
+}
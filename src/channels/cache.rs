@@ -0,0 +1,214 @@
+use crate::channels::Category;
+use crate::channels::actor_channel::DailyChannelManager;
+use crate::utils::hash_string;
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+//
+// Policy of the cache of the imported daily channels, shared by the whole tree.
+// When the capacity is reached the least recently used channel is dropped,
+// channels not used for longer than the ttl are dropped on the next access.
+// A channel is never dropped while a handle to it is still held, otherwise the next request
+// would import a second writer of the same channel: the cache can exceed its capacity until they are released
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy{
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+}
+
+impl CachePolicy{
+    pub fn new() -> Self {
+        CachePolicy{ capacity: Some(DEFAULT_CACHE_CAPACITY), ttl: None }
+    }
+
+    pub fn unbounded() -> Self {
+        CachePolicy{ capacity: None, ttl: None }
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn max_entries(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub fn max_idle(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
+
+impl Default for CachePolicy{
+    fn default() -> Self {
+        CachePolicy::new()
+    }
+}
+
+//
+// Evictions count only the channels dropped by the policy, not the ones removed explicitly
+//
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CacheStats{
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub (crate) struct CacheKey{
+    pub category: String,
+    pub actor_id: String,
+    pub date: NaiveDate,
+    pub psw_hash: String,
+}
+
+impl CacheKey{
+    pub (crate) fn new(category: &Category, actor_id: &str, date: NaiveDate, state_psw: &str) -> Self {
+        CacheKey{
+            category: category.id().to_string(),
+            actor_id: actor_id.to_lowercase(),
+            date,
+            psw_hash: hash_string(state_psw),
+        }
+    }
+}
+
+struct CacheEntry{
+    manager: DailyChannelManager,
+    last_used: Instant,
+}
+
+impl CacheEntry{
+    fn expired(&self, ttl: Option<Duration>) -> bool {
+        matches!(ttl, Some(ttl) if self.last_used.elapsed() > ttl) && !self.manager.in_use()
+    }
+}
+
+struct CacheInner{
+    policy: CachePolicy,
+    entries: HashMap<CacheKey, CacheEntry>,
+    stats: CacheStats,
+}
+
+pub (crate) struct DailyChannelCache{
+    inner: Mutex<CacheInner>,
+}
+
+impl DailyChannelCache{
+    pub (crate) fn new(policy: CachePolicy) -> Self {
+        let inner = CacheInner{ policy, entries: HashMap::new(), stats: CacheStats::default() };
+        DailyChannelCache{ inner: Mutex::new(inner) }
+    }
+
+    pub (crate) async fn get(&self, key: &CacheKey) -> Option<DailyChannelManager>{
+        let mut inner = self.inner.lock().await;
+        let ttl = inner.policy.ttl;
+        let expired = match inner.entries.get(key){
+            None => {
+                inner.stats.misses += 1;
+                return None;
+            }
+            Some(entry) => entry.expired(ttl)
+        };
+
+        if expired{
+            inner.entries.remove(key);
+            inner.stats.evictions += 1;
+            inner.stats.misses += 1;
+            return None;
+        }
+
+        inner.stats.hits += 1;
+        inner.entries.get_mut(key).map(|entry| {
+            entry.last_used = Instant::now();
+            entry.manager.clone()
+        })
+    }
+
+    pub (crate) async fn insert(&self, key: CacheKey, manager: DailyChannelManager){
+        let mut inner = self.inner.lock().await;
+        inner.entries.insert(key, CacheEntry{ manager, last_used: Instant::now() });
+        inner.trim();
+    }
+
+    //
+    // Only the channels without handles still held are removed
+    //
+    pub (crate) async fn evict<F: Fn(&CacheKey) -> bool>(&self, filter: F) -> usize{
+        let mut inner = self.inner.lock().await;
+        let before = inner.entries.len();
+        inner.entries.retain(|key, entry| !filter(key) || entry.manager.in_use());
+        before - inner.entries.len()
+    }
+
+    //
+    // Move a channel under a new key, the old key doesn't give it anymore
+    //
+    pub (crate) async fn rekey(&self, old_key: &CacheKey, new_key: CacheKey){
+        let mut inner = self.inner.lock().await;
+        if let Some(entry) = inner.entries.remove(old_key){
+            inner.entries.insert(new_key, CacheEntry{ manager: entry.manager, last_used: Instant::now() });
+        }
+    }
+
+    pub (crate) async fn clear(&self) -> usize{
+        self.evict(|_| true).await
+    }
+
+    pub (crate) async fn entries(&self) -> Vec<(CacheKey, DailyChannelManager)>{
+        let inner = self.inner.lock().await;
+        inner.entries.iter().map(|(key, entry)| (key.clone(), entry.manager.clone())).collect()
+    }
+
+    pub (crate) async fn set_policy(&self, policy: CachePolicy){
+        let mut inner = self.inner.lock().await;
+        inner.policy = policy;
+        inner.trim();
+    }
+
+    pub (crate) async fn stats(&self) -> CacheStats{
+        let inner = self.inner.lock().await;
+        CacheStats{ entries: inner.entries.len(), ..inner.stats }
+    }
+}
+
+impl CacheInner{
+    fn trim(&mut self){
+        if let Some(ttl) = self.policy.ttl{
+            let before = self.entries.len();
+            self.entries.retain(|_, entry| !entry.expired(Some(ttl)));
+            self.stats.evictions += (before - self.entries.len()) as u64;
+        }
+
+        let capacity = match self.policy.capacity{
+            None => return,
+            Some(capacity) => capacity
+        };
+        while self.entries.len() > capacity{
+            let lru = self.entries.iter()
+                .filter(|(_, entry)| !entry.manager.in_use())
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match lru{
+                None => break,
+                Some(key) => {
+                    self.entries.remove(&key);
+                    self.stats.evictions += 1;
+                }
+            }
+        }
+    }
+}
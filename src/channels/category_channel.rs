@@ -1,8 +1,18 @@
-use crate::channels::{Category, create_channel, ChannelInfo, create_reader, node_url};
+use crate::channels::{Category, ChannelInfo};
+use crate::channels::errors::{self, ChannelManagerError, ChannelLayer};
 use crate::channels::actor_channel::{ActorChannel, DailyChannelManager, DailyChannelMsg};
+use crate::channels::import_options::{ImportRun, ImportProgress};
+use crate::channels::cache::DailyChannelCache;
+use crate::channels::backup::{CategoryBackup, WriterBackup};
+use crate::utils::Tz;
+use chrono::NaiveDate;
+use crate::transport::{StreamsWriter, Transport, send_public_packet, fetch_public_packets};
 use serde::{Serialize, Deserialize};
-use iota_streams_lib::payload::payload_serializers::{JsonPacketBuilder, JsonPacket};
-use iota_streams_lib::channels::ChannelWriter;
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures::{stream, StreamExt, TryStreamExt};
+use tracing::{debug, info};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ActorChannelMsg{
@@ -27,39 +37,54 @@ impl ActorChannelMsg{
     }
 }
 
+//
+// Actors are kept as the messages announced on the category channel and imported from the tangle
+// only the first time they are used, like the actor channel does with its daily channels
+//
 pub (crate) struct CategoryChannel{
     category: Category,
-    channel: ChannelWriter,
-    actors: Vec<ActorChannel>,
-    mainnet: bool
+    channel: Box<dyn StreamsWriter>,
+    actors: Vec<ActorChannelMsg>,
+    imported_actors: HashMap<String, ActorChannel>,
+    timezone: Tz,
+    transport: Arc<dyn Transport>,
+    daily_channels_cache: Arc<DailyChannelCache>,
 }
 
 #[allow(dead_code)]
 impl CategoryChannel {
-    pub (crate) fn new(category: Category, mainnet: bool) -> Self {
-        let channel = create_channel(mainnet);
-        CategoryChannel { category, channel, actors: vec![], mainnet }
+    pub (crate) fn new(category: Category, timezone: Tz, transport: Arc<dyn Transport>, daily_channels_cache: Arc<DailyChannelCache>) -> Self {
+        let channel = transport.create_writer();
+        CategoryChannel { category, channel, actors: vec![], imported_actors: HashMap::new(), timezone, transport, daily_channels_cache }
     }
 
-    pub (crate) async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, category: Category, mainnet: bool) -> anyhow::Result<Self>{
-        let node_url = node_url(mainnet);
-        let channel = ChannelWriter::import_from_tangle(channel_id, announce_id, state_psw, Some(node_url.as_str()), None).await?;
-        let actors_info = CategoryChannel::read_actors_channels_info(channel_id, announce_id, mainnet).await?;
-        let mut actors = vec![];
-        for a in actors_info {
-            let ch = ActorChannel::import_from_tangle(
-                &a.address.channel_id,
-                &a.address.announce_id,
-                state_psw,
-                category.clone(),
-                a.actor_id(),
-                mainnet).await?;
-            actors.push(ch);
+    //
+    // Only the actor announcements are read, unless the options ask to preload the actors.
+    // The category channel and every preloaded actor hold a permit of the semaphore shared by the whole tree while they are fetched,
+    // the one of the category is released before its actors are restored
+    //
+    #[allow(clippy::too_many_arguments)]
+    pub (crate) async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, category: Category, timezone: Tz, transport: Arc<dyn Transport>,
+                                            daily_channels_cache: Arc<DailyChannelCache>, run: &ImportRun<'_>) -> errors::Result<Self>{
+        let (channel, actors) = {
+            let _permit = run.permits.acquire().await
+                .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Category, format!("{}:{}", channel_id, announce_id), err.into()))?;
+            let channel = transport.import_from_tangle(channel_id, announce_id, state_psw).await
+                .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Category, format!("{}:{}", channel_id, announce_id), err))?;
+            (channel, CategoryChannel::read_actors_channels_info(channel_id, announce_id, transport.as_ref()).await?)
+        };
+
+        let total = actors.len();
+        if !run.options.preloads_actors(){
+            run.options.report(ImportProgress{ category: category.clone(), restored: 0, deferred: total, total });
+            return Ok( CategoryChannel{ category, channel, actors, imported_actors: HashMap::new(), timezone, transport, daily_channels_cache } )
         }
-        Ok( CategoryChannel{ category, channel, actors, mainnet } )
+
+        let imported_actors = CategoryChannel::import_actors(&actors, state_psw, &category, timezone, &transport, &daily_channels_cache, run).await?;
+        Ok( CategoryChannel{ category, channel, actors, imported_actors, timezone, transport, daily_channels_cache } )
     }
 
-    pub (crate) async fn open(&mut self, channel_psw: &str) -> anyhow::Result<ChannelInfo> {
+    pub (crate) async fn open(&mut self, channel_psw: &str) -> errors::Result<ChannelInfo> {
         let info = self.channel.open_and_save(channel_psw).await?;
         Ok(ChannelInfo::new(info.0, info.1))
     }
@@ -68,41 +93,98 @@ impl CategoryChannel {
         &self.category
     }
 
-    pub (crate) async fn new_daily_actor_channel(&mut self, actor_id: &str, root_psw: &str, state_psw: &str,
-                                                   day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
-        let exist = self.actors.iter().any(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase());
+    //
+    // The actors not loaded yet are imported with the password of the writers on the tangle before being saved,
+    // the backup must not depend on the tangle. The writer states are exported with the password of the backup
+    //
+    pub (crate) async fn backup(&mut self, root_psw: &str, backup_psw: &str, run: &ImportRun<'_>) -> errors::Result<CategoryBackup>{
+        let not_loaded: Vec<ActorChannelMsg> = self.actors.iter()
+            .filter(|a| !self.imported_actors.contains_key(a.actor_id()))
+            .cloned()
+            .collect();
+        let imported = CategoryChannel::import_actors(&not_loaded, root_psw, &self.category, self.timezone, &self.transport, &self.daily_channels_cache, run).await?;
+        self.imported_actors.extend(imported);
+        let actor_channels = self.actors.iter()
+            .map(|a| self.imported_actors.get(a.actor_id())
+                .ok_or_else(|| ChannelManagerError::ActorNotFound(a.actor_id().to_string()))?
+                .backup(backup_psw))
+            .collect::<errors::Result<Vec<_>>>()?;
+        let writer = WriterBackup{ address: self.channel_info(), state: self.channel.export_to_bytes(backup_psw)? };
+        Ok(CategoryBackup{ category: self.category.clone(), writer, actors: self.actors.clone(), actor_channels })
+    }
+
+    pub (crate) async fn restore(backup: &CategoryBackup, root_psw: &str, timezone: Tz, transport: Arc<dyn Transport>,
+                                 daily_channels_cache: Arc<DailyChannelCache>) -> errors::Result<Self>{
+        let channel = transport.import_from_bytes(&backup.writer.state, root_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Category, backup.writer.address.to_string(), err))?;
+        let mut imported_actors = HashMap::new();
+        for actor in backup.actor_channels.iter() {
+            let ch = ActorChannel::restore(actor, root_psw, backup.category.clone(), timezone, transport.clone(), daily_channels_cache.clone()).await?;
+            imported_actors.insert(actor.actor_id.clone(), ch);
+        }
+        Ok( CategoryChannel{
+            category: backup.category.clone(),
+            channel,
+            actors: backup.actors.clone(),
+            imported_actors,
+            timezone,
+            transport,
+            daily_channels_cache
+        } )
+    }
+
+    //
+    // Add the actors announced on the tangle that are not known yet and the new daily channels of the loaded actors
+    //
+    pub (crate) async fn resync(&mut self) -> errors::Result<usize>{
+        let info = self.channel_info();
+        let announced = CategoryChannel::read_actors_channels_info(info.channel_id(), info.announce_id(), self.transport.as_ref()).await?;
+        let mut added = 0;
+        for msg in announced {
+            if !self.actors.iter().any(|a| a.actor_id() == msg.actor_id()){
+                self.actors.push(msg);
+                added += 1;
+            }
+        }
+        for actor in self.imported_actors.values_mut() {
+            added += actor.resync().await?;
+        }
+        Ok(added)
+    }
+
+    pub (crate) async fn new_daily_actor_channel(&mut self, actor_id: &str, root_psw: &str, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let exist = self.actors.iter().any(|ch| ch.actor_id() == actor_id.to_lowercase());
         if !exist{
             self.create_actor_channel(actor_id, root_psw).await?;
         }
 
-        self.actors.iter_mut()
-            .find(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase()).unwrap()
-            .new_daily_actor_channel(state_psw, day, month, year).await
+        self.actor_channel(actor_id, root_psw).await?
+            .new_daily_actor_channel(state_psw, date).await
     }
 
-    pub (crate) async fn get_daily_actor_channel(&mut self, actor_id: &str, state_psw: &str,
-                                         day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
+    pub (crate) async fn get_daily_actor_channel(&mut self, actor_id: &str, root_psw: &str, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        self.actor_channel(actor_id, root_psw).await?
+            .get_daily_channel_in_date(state_psw, date).await
+    }
 
-        let exist = self.actors.iter().any(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase());
+    pub (crate) async fn daily_actor_channel_for(&mut self, actor_id: &str, root_psw: &str, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let exist = self.actors.iter().any(|ch| ch.actor_id() == actor_id.to_lowercase());
         if !exist{
-            return Err(anyhow::Error::msg(format!("Actor {} doesn't exist yet", actor_id)));
+            self.create_actor_channel(actor_id, root_psw).await?;
         }
 
-        self.actors.iter_mut()
-            .find(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase()).unwrap()
-            .get_daily_channel_in_date(state_psw, day, month, year).await
+        self.actor_channel(actor_id, root_psw).await?
+            .daily_channel_for(state_psw, date).await
     }
 
-    pub (crate) async fn serialize_daily_actor_channel(&mut self, actor_id: &str, state_psw: &str,
-                                                       day: u16, month: u16, year: u16) -> anyhow::Result<String>{
-        let exist = self.actors.iter().any(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase());
-        if !exist{
-            return Err(anyhow::Error::msg(format!("Actor {} doesn't exist yet", actor_id)));
-        }
+    pub (crate) async fn serialize_daily_actor_channel(&mut self, actor_id: &str, root_psw: &str, state_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        self.actor_channel(actor_id, root_psw).await?
+            .serialize_daily_channel(state_psw, date).await
+    }
 
-        self.actors.iter_mut()
-            .find(|ch| ch.actor_id().to_lowercase() == actor_id.to_lowercase()).unwrap()
-            .serialize_daily_channel(state_psw, day, month, year).await
+    pub (crate) async fn rotate_daily_channel_password(&mut self, actor_id: &str, root_psw: &str, old_psw: &str, new_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        self.actor_channel(actor_id, root_psw).await?
+            .rotate_daily_channel_password(old_psw, new_psw, date).await
     }
 
     pub (crate) fn channel_info(&self) -> ChannelInfo{
@@ -110,52 +192,111 @@ impl CategoryChannel {
         ChannelInfo::new(info.0, info.1)
     }
 
-    pub (crate) fn print_nested_channel_info(&self){
+    //
+    // Actors that have not been used yet are printed without their daily channels
+    //
+    pub (crate) fn print_nested_channel_info(&self, last: bool){
         let info = self.channel_info();
-        let category = match self.category{
-            Category::Trucks => "Trucks",
-            Category::Scales => "Scales",
-            Category::BioCells => "BioCells"
-        };
+        let network = self.transport.network();
+        let prefix = if last { "    " } else { "|   " };
 
-        println!("|--{} = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", category, info.channel_id, info.announce_id, self.mainnet);
-        self.actors.iter().for_each(|a| a.print_nested_channel_info());
+        println!("|--{} = {}", self.category.explorer_label(), info.explorer_url(network));
+        self.actors.iter().for_each(|a| match self.imported_actors.get(a.actor_id()){
+            Some(actor) => actor.print_nested_channel_info(prefix),
+            None => println!("{}|--Actor {} = {} (not loaded)", prefix, a.actor_id(), a.address().explorer_url(network)),
+        });
     }
 }
 
 impl CategoryChannel{
-    async fn create_actor_channel(&mut self, actor_id: &str, state_psw: &str) -> anyhow::Result<()>{
-        let found = self.actors.iter()
-            .filter(|a| {actor_id.to_lowercase() == a.actor_id().to_string()})
-            .count();
-        if found > 0{
-            return Err(anyhow::Error::msg("Actor channel with this id already exist"));
+    //
+    // Returns the actor channel, importing it from the tangle the first time it is requested
+    //
+    async fn actor_channel(&mut self, actor_id: &str, root_psw: &str) -> errors::Result<&mut ActorChannel>{
+        let actor_id = actor_id.to_lowercase();
+        if !self.imported_actors.contains_key(&actor_id){
+            let msg = self.actors.iter()
+                .find(|a| a.actor_id() == actor_id)
+                .cloned()
+                .ok_or_else(|| ChannelManagerError::ActorNotFound(actor_id.clone()))?;
+            let ch = ActorChannel::import_from_tangle(
+                msg.address().channel_id(),
+                msg.address().announce_id(),
+                root_psw,
+                self.category.clone(),
+                &actor_id,
+                self.timezone,
+                self.transport.clone(),
+                self.daily_channels_cache.clone()).await?;
+            debug!(category = %self.category.to_string(), actor_id = %actor_id, address = %msg.address().to_string(), "actor loaded on first use");
+            self.imported_actors.insert(actor_id.clone(), ch);
         }
-        let mut actor_channel = ActorChannel::new(self.category.clone(), actor_id, self.mainnet);
+        self.imported_actors.get_mut(&actor_id)
+            .ok_or(ChannelManagerError::ActorNotFound(actor_id))
+    }
+
+    //
+    // Import the actors from the tangle in parallel, every actor holds a permit of the run while it is fetched
+    // and the progress is reported every time one of them has been restored
+    //
+    async fn import_actors(actors: &[ActorChannelMsg], state_psw: &str, category: &Category, timezone: Tz, transport: &Arc<dyn Transport>,
+                           daily_channels_cache: &Arc<DailyChannelCache>, run: &ImportRun<'_>) -> errors::Result<HashMap<String, ActorChannel>>{
+        let options = run.options;
+        let total = actors.len();
+        let restored = AtomicUsize::new(0);
+        stream::iter(actors.iter())
+            .map(|a| {
+                let (transport, restored) = (transport.clone(), &restored);
+                let cache = daily_channels_cache.clone();
+                async move {
+                    let _permit = run.permits.acquire().await
+                        .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Actor, a.address().to_string(), err.into()))?;
+                    let ch = ActorChannel::import_from_tangle(
+                        &a.address.channel_id,
+                        &a.address.announce_id,
+                        state_psw,
+                        category.clone(),
+                        a.actor_id(),
+                        timezone,
+                        transport,
+                        cache).await?;
+                    debug!(category = %category.to_string(), actor_id = %a.actor_id(), address = %a.address().to_string(), "actor imported");
+                    let restored = restored.fetch_add(1, Ordering::SeqCst) + 1;
+                    options.report(ImportProgress{ category: category.clone(), restored, deferred: 0, total });
+                    Ok::<_, ChannelManagerError>((a.actor_id().to_string(), ch))
+                }
+            })
+            .buffered(options.max_concurrency())
+            .try_collect::<HashMap<_, _>>()
+            .await
+    }
+
+    async fn create_actor_channel(&mut self, actor_id: &str, state_psw: &str) -> errors::Result<()>{
+        let actor_id = actor_id.to_lowercase();
+        if self.actors.iter().any(|a| a.actor_id() == actor_id){
+            return Err(ChannelManagerError::ActorExists(actor_id));
+        }
+        let mut actor_channel = ActorChannel::new(self.category.clone(), &actor_id, self.timezone, self.transport.clone(), self.daily_channels_cache.clone());
         let info = actor_channel.open(state_psw).await?;
-        self.actors.push(actor_channel);
 
-        self.publish_actor_channel(info, actor_id).await?;
+        info!(category = %self.category.to_string(), actor_id = %actor_id, address = %info.to_string(), "actor channel created");
+        let msg = self.publish_actor_channel(info, &actor_id).await?;
+        self.actors.push(msg);
+        self.imported_actors.insert(actor_id, actor_channel);
         Ok(())
     }
 
-    async fn publish_actor_channel(&mut self, info: ChannelInfo, actor_id: &str) -> anyhow::Result<()>{
+    async fn publish_actor_channel(&mut self, info: ChannelInfo, actor_id: &str) -> errors::Result<ActorChannelMsg>{
         let msg = ActorChannelMsg::new(info, self.category.clone(), actor_id);
-        let packet = JsonPacketBuilder::new()
-            .public(&msg)?
-            .build();
-        self.channel.send_signed_packet(&packet).await?;
-        Ok(())
+        send_public_packet(self.channel.as_mut(), &msg).await?;
+        Ok(msg)
     }
 
-    async fn read_actors_channels_info(channel_id: &str, announce_id: &str, mainnet: bool) -> anyhow::Result<Vec<ActorChannelMsg>>{
-        let mut reader = create_reader(channel_id, announce_id, mainnet);
+    async fn read_actors_channels_info(channel_id: &str, announce_id: &str, transport: &dyn Transport) -> errors::Result<Vec<ActorChannelMsg>>{
+        let mut reader = transport.create_reader(channel_id, announce_id);
         reader.attach().await?;
-        let msgs: Vec<(String, JsonPacket)> = reader.fetch_parsed_msgs(&None).await?;
-        let mut actors = vec![];
-        for (_, m) in msgs {
-            actors.push(m.deserialize_public()?);
-        }
+        let msgs = fetch_public_packets(reader.as_mut()).await?;
+        let actors = msgs.into_iter().map(|(_, m)| m).collect();
         Ok(actors)
     }
 }
@@ -163,15 +304,10 @@ impl CategoryChannel{
 // Read APIs
 impl CategoryChannel {
     pub fn actors_info(&self) -> Vec<ActorChannelMsg>{
-        self.actors.iter().map(|a| {
-            ActorChannelMsg::new(a.channel_info(), self.category.clone(), a.actor_id())
-        })
-            .collect()
+        self.actors.clone()
     }
 
-    pub fn channels_of_actor(&self, actor_id: &str) -> Vec<DailyChannelMsg>{
-        self.actors.iter()
-            .find(|a| a.actor_id().to_lowercase() == actor_id.to_lowercase())
-            .map_or(vec![], |a| a.daily_channels_info())
+    pub async fn channels_of_actor(&mut self, actor_id: &str, root_psw: &str) -> errors::Result<Vec<DailyChannelMsg>>{
+        Ok(self.actor_channel(actor_id, root_psw).await?.daily_channels_info())
     }
 }
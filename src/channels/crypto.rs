@@ -0,0 +1,140 @@
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::utils::hash_string;
+use aead::generic_array::GenericArray;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::XChaCha20Poly1305;
+use argon2::{Argon2, Algorithm, Version, Params};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use zeroize::{Zeroize, Zeroizing};
+
+pub (crate) const SALT_LEN: usize = 16;
+pub (crate) const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_PARALLELISM: u32 = 1;
+// Highest costs accepted from a header, it is read before being authenticated
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 * ARGON2_MEMORY_KIB;
+const MAX_ARGON2_ITERATIONS: u32 = 4 * ARGON2_ITERATIONS;
+const MAX_ARGON2_PARALLELISM: u32 = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub (crate) enum KdfAlgorithm{
+    Argon2id,
+}
+
+//
+// Parameters of the key derivation, stored next to the ciphertext so that the costs can be raised
+// without breaking the states already exported
+//
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub (crate) struct KdfParams{
+    algorithm: KdfAlgorithm,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: [u8; SALT_LEN],
+}
+
+impl KdfParams{
+    pub (crate) fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams::with_salt(salt)
+    }
+
+    fn with_salt(salt: [u8; SALT_LEN]) -> Self {
+        KdfParams{
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+            salt,
+        }
+    }
+
+    pub (crate) fn derive_key(&self, psw: &str) -> errors::Result<Zeroizing<[u8; KEY_LEN]>>{
+        if self.memory_kib > MAX_ARGON2_MEMORY_KIB || self.iterations > MAX_ARGON2_ITERATIONS || self.parallelism > MAX_ARGON2_PARALLELISM{
+            return Err(ChannelManagerError::StateCorrupted(format!(
+                "Key derivation costs out of range (memory {} KiB, {} iterations, parallelism {})", self.memory_kib, self.iterations, self.parallelism
+            )));
+        }
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN))
+            .map_err(|e| ChannelManagerError::StateCorrupted(e.to_string()))?;
+        let algorithm = match self.algorithm{
+            KdfAlgorithm::Argon2id => Algorithm::Argon2id
+        };
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(algorithm, Version::V0x13, params)
+            .hash_password_into(psw.as_bytes(), &self.salt, &mut key[..])
+            .map_err(|e| ChannelManagerError::StateCorrupted(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+pub (crate) fn random_nonce() -> [u8; NONCE_LEN]{
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+pub (crate) fn encrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], msg: &[u8], aad: &[u8]) -> errors::Result<Vec<u8>>{
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    chacha.encrypt(GenericArray::from_slice(nonce), Payload{ msg, aad })
+        .map_err(|_| ChannelManagerError::StateCorrupted("Error during state encryption".to_string()))
+}
+
+pub (crate) fn decrypt(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], msg: &[u8], aad: &[u8]) -> errors::Result<Zeroizing<Vec<u8>>>{
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    chacha.decrypt(GenericArray::from_slice(nonce), Payload{ msg, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| ChannelManagerError::WrongPassword)
+}
+
+//
+// States exported before the envelope: the bare ciphertext, with key and nonce derived from the password hash
+//
+pub (crate) fn open_legacy(sealed: &[u8], psw: &str) -> errors::Result<Zeroizing<Vec<u8>>>{
+    let (key, nonce) = bare_key_nonce(psw);
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+    chacha.decrypt(GenericArray::from_slice(&nonce), sealed)
+        .map(Zeroizing::new)
+        .map_err(|_| ChannelManagerError::WrongPassword)
+}
+
+//
+// Seal a state as the releases before the envelope did, only to check that they can still be read
+//
+#[cfg(test)]
+pub (crate) fn seal_legacy(plain: &[u8], psw: &str) -> Vec<u8>{
+    let (key, nonce) = bare_key_nonce(psw);
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+    chacha.encrypt(GenericArray::from_slice(&nonce), plain).unwrap()
+}
+
+fn bare_key_nonce(psw: &str) -> (Zeroizing<Vec<u8>>, Vec<u8>) {
+    let mut psw_hash = hash_string(psw);
+    let mut key_hash = psw_hash[..32].to_string();
+    let nonce_hash = &hash_string(&key_hash)[..24];
+    let key = Zeroizing::new(key_hash.as_bytes().to_vec());
+    let nonce = nonce_hash.as_bytes().to_vec();
+    psw_hash.zeroize();
+    key_hash.zeroize();
+    (key, nonce)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn kdf_costs_out_of_range_are_rejected(){
+        let kdf = KdfParams{ memory_kib: u32::MAX, ..KdfParams::generate() };
+        assert!(matches!(kdf.derive_key("psw"), Err(ChannelManagerError::StateCorrupted(_))));
+        let kdf = KdfParams{ iterations: u32::MAX, ..KdfParams::generate() };
+        assert!(matches!(kdf.derive_key("psw"), Err(ChannelManagerError::StateCorrupted(_))));
+    }
+}
@@ -1,160 +1,139 @@
-use crate::channels::{Category, create_channel, ChannelInfo, node_url};
-use crate::utils::{current_time_secs, timestamp_to_date_string, hash_string};
+use crate::channels::{Category, ChannelInfo};
+use crate::channels::errors::{self, ChannelManagerError, ChannelLayer};
+use crate::channels::state_format::DailyChannelState;
+use crate::channels::backup::DailyChannelBackup;
+use crate::utils::{current_time_secs, start_of_day, Tz};
+use crate::transport::{StreamsWriter, Transport, TangleTransport};
 use chrono::NaiveDate;
-use iota_streams_lib::channels::ChannelWriter;
-use serde::{Serialize, Deserialize};
-use aead::generic_array::GenericArray;
-use chacha20poly1305::aead::{Aead, NewAead};
-use chacha20poly1305::XChaCha20Poly1305;
-use base64::{encode_config, URL_SAFE_NO_PAD, decode_config};
+use std::sync::Arc;
+use tracing::{info, instrument};
 
 #[allow(dead_code)]
 pub (crate) struct DailyChannel{
     category: Category,
     actor_id: String,
-    channel: ChannelWriter,
+    channel: Box<dyn StreamsWriter>,
     creation_timestamp: i64,
-    mainnet: bool
+    timezone: Tz,
+    transport: Arc<dyn Transport>
 }
 
 impl DailyChannel{
-    pub (crate) fn new(category: Category, actor_id: &str, mainnet: bool) -> Self {
+    pub (crate) fn new(category: Category, actor_id: &str, timezone: Tz, transport: Arc<dyn Transport>) -> Self {
         let creation_timestamp = current_time_secs();
-        let channel = create_channel(mainnet);
-        DailyChannel { category, actor_id: actor_id.to_lowercase(), channel, creation_timestamp, mainnet}
+        let channel = transport.create_writer();
+        DailyChannel { category, actor_id: actor_id.to_lowercase(), channel, creation_timestamp, timezone, transport}
     }
 
-    pub (crate) fn new_in_date(category: Category, actor_id: &str, day: u16, month: u16, year: u16, mainnet: bool) -> anyhow::Result<Self>{
-        let mut ch = DailyChannel::new(category, actor_id, mainnet);
-        let date = match NaiveDate::from_ymd(year as i32, month as u32, day as u32)
-            .and_hms_opt(0, 0, 0){
-            None => return Err(anyhow::Error::msg("Invalid date")),
-            Some(date) => date
-        };
-        ch.creation_timestamp = date.timestamp();
+    //
+    // The creation timestamp is the instant the day starts in the timezone of the plant
+    //
+    pub (crate) fn new_in_date(category: Category, actor_id: &str, date: NaiveDate, timezone: Tz, transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        let mut ch = DailyChannel::new(category, actor_id, timezone, transport);
+        ch.creation_timestamp = start_of_day(date, &timezone).ok_or(ChannelManagerError::InvalidDate)?;
         Ok(ch)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub (crate) async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, category: Category,
-                                    actor_id: &str, creation_timestamp: i64, mainnet: bool) -> anyhow::Result<Self>{
-        let node_url = node_url(mainnet);
-        let channel = ChannelWriter::import_from_tangle(channel_id, announce_id, state_psw, Some(node_url.as_str()), None).await?;
-        Ok(DailyChannel{ category, actor_id: actor_id.to_lowercase(), channel, creation_timestamp, mainnet})
+                                    actor_id: &str, creation_timestamp: i64, timezone: Tz, transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        let channel = transport.import_from_tangle(channel_id, announce_id, state_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Daily, format!("{}:{}", channel_id, announce_id), err))?;
+        Ok(DailyChannel{ category, actor_id: actor_id.to_lowercase(), channel, creation_timestamp, timezone, transport})
     }
 
-    pub (crate) async fn open(&mut self, state_psw: &str) -> anyhow::Result<ChannelInfo>{
+    pub (crate) async fn open(&mut self, state_psw: &str) -> errors::Result<ChannelInfo>{
         let info = self.channel.open_and_save(state_psw).await?;
         Ok(ChannelInfo::new(info.0, info.1))
     }
 
-    pub (crate) fn export_to_base64(&self, state_psw: &str) -> anyhow::Result<String>{
-        let state = DailyChannelState::new(state_psw, &self)?;
-        state.encrypt()
+    pub (crate) fn export_to_base64(&self, state_psw: &str) -> errors::Result<String>{
+        let state = DailyChannelState::new(
+            self.channel.export_to_bytes(state_psw)?,
+            self.category.clone(),
+            &self.actor_id,
+            self.creation_timestamp,
+            self.timezone,
+            self.transport.network().clone()
+        );
+        state.encrypt(state_psw)
     }
-}
 
-impl DailyChannel{
-    pub async fn send_raw_packet(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> anyhow::Result<String>{
-        self.channel.send_signed_raw_data(p_data, m_data, key_nonce).await
+    pub (crate) fn export_writer(&self, psw: &str) -> errors::Result<Vec<u8>>{
+        Ok(self.channel.export_to_bytes(psw)?)
     }
 
-    pub (crate) fn creation_timestamp(&self) -> i64 {
-        self.creation_timestamp
+    pub (crate) async fn restore(backup: &DailyChannelBackup, psw: &str, timezone: Tz, transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        let channel = transport.import_from_bytes(&backup.writer.state, psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Daily, backup.writer.address.to_string(), err))?;
+        Ok(DailyChannel{
+            category: backup.category.clone(),
+            actor_id: backup.actor_id.clone(),
+            channel,
+            creation_timestamp: backup.creation_timestamp,
+            timezone,
+            transport
+        })
     }
 
-    pub (crate) fn creation_date(&self) -> String{
-        timestamp_to_date_string(self.creation_timestamp, false)
+    pub (crate) async fn export_with_new_password(&self, old_psw: &str, new_psw: &str) -> errors::Result<String>{
+        let info = self.channel_info();
+        self.transport.import_from_tangle(info.channel_id(), info.announce_id(), old_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Daily, info.to_string(), err))?;
+        self.export_to_base64(new_psw)
     }
+}
 
-    pub (crate) fn channel_info(&self) -> ChannelInfo{
-        let info = self.channel.channel_address();
-        ChannelInfo::new(info.0, info.1)
+impl DailyChannel{
+    pub async fn send_raw_packet(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        Ok(self.channel.send_signed_raw_data(p_data, m_data, key_nonce).await?)
     }
 
-    pub (crate) async fn import_from_base64(state: &str, state_psw: &str) -> anyhow::Result<Self>{
-        println!("Trying to import daily channel from base64...");
-        let state = DailyChannelState::decrypt(state, state_psw)?;
-        let ch = state.to_daily_channel().await?;
-        println!("  ...Daily Channel Import complete");
-        Ok(ch)
+    pub (crate) fn creation_timestamp(&self) -> i64 {
+        self.creation_timestamp
     }
-}
 
-#[derive(Serialize, Deserialize)]
-struct DailyChannelState{
-    channel_state: Vec<u8>,
-    category: Category,
-    actor_id: String,
-    creation_timestamp: i64,
-    state_psw: String,
-    mainnet: bool
-}
-
-impl DailyChannelState {
-    fn new(state_psw: &str, channel: &DailyChannel) -> anyhow::Result<Self>{
-        let channel_state = channel.channel.export_to_bytes(state_psw)?;
-        let category = channel.category.clone();
-        let actor_id = channel.actor_id.clone();
-        let creation_timestamp = channel.creation_timestamp;
-        let state_psw = state_psw.to_string();
-        let mainnet = channel.mainnet;
-        Ok(DailyChannelState{channel_state, category, actor_id, creation_timestamp, state_psw, mainnet})
+    pub (crate) fn category(&self) -> &Category {
+        &self.category
     }
 
-    pub fn encrypt(&self) -> anyhow::Result<String>{
-        let psw = &self.state_psw;
-        let bytes = bincode::serialize(&self)?;
-
-        let (key, nonce) = key_nonce(psw);
-        let key = GenericArray::from_slice(&key[..]);
-        let nonce = GenericArray::from_slice(&nonce[..]);
-
-        let chacha = XChaCha20Poly1305::new(key);
-        let enc = chacha.encrypt(nonce, bytes.as_ref())
-            .map_err(|_| anyhow::Error::msg("Error during state encryption"))?;
-
-        let base64 = encode_config(&enc, URL_SAFE_NO_PAD);
-        Ok(base64)
+    pub (crate) fn timezone(&self) -> Tz {
+        self.timezone
     }
 
-    pub fn decrypt(base64: &str, psw: &str) -> anyhow::Result<Self>{
-        let bytes = decode_config(&base64.as_bytes().to_vec(), URL_SAFE_NO_PAD)?;
-
-        let (key, nonce) = key_nonce(psw);
-        let key = GenericArray::from_slice(&key[..]);
-        let nonce = GenericArray::from_slice(&nonce[..]);
-
-        let chacha = XChaCha20Poly1305::new(key);
-        let dec = chacha.decrypt(nonce, bytes.as_ref())
-            .map_err(|_| anyhow::Error::msg("Error during state decryption"))?;
-
-        let ch_state: DailyChannelState = bincode::deserialize(&dec)?;
-        assert_eq!(psw.to_string(), ch_state.state_psw);
-        Ok(ch_state)
+    pub (crate) fn channel_info(&self) -> ChannelInfo{
+        let info = self.channel.channel_address();
+        ChannelInfo::new(info.0, info.1)
     }
 
-    pub async fn to_daily_channel(&self) -> anyhow::Result<DailyChannel>{
-        let writer = ChannelWriter::import_from_bytes(
-            &self.channel_state,
-            &self.state_psw,
-            Some(&node_url(self.mainnet)),
-            None
-        ).await?;
-        let daily_ch = DailyChannel{
-            category: self.category.clone(),
-            actor_id: self.actor_id.clone(),
-            channel: writer,
-            creation_timestamp: self.creation_timestamp,
-            mainnet: self.mainnet
+    #[instrument(name = "import_daily_channel_base64", skip_all)]
+    pub (crate) async fn import_from_base64(state: &str, state_psw: &str, transport: Option<Arc<dyn Transport>>) -> errors::Result<Self>{
+        let state = DailyChannelState::decrypt(state, state_psw)?;
+        if state.is_outdated(){
+            info!(format_version = state.format_version, "daily channel state in an old format, export it again to upgrade it");
+        }
+        let transport = transport.unwrap_or_else(|| Arc::new(TangleTransport::new(state.network.clone())));
+        let channel = transport.import_from_bytes(&state.channel_state, state_psw).await?;
+        let ch = DailyChannel{
+            category: state.category.clone(),
+            actor_id: state.actor_id.clone(),
+            channel,
+            creation_timestamp: state.creation_timestamp,
+            timezone: state.timezone,
+            transport
         };
-        Ok(daily_ch)
+        info!(
+            category = %ch.category.to_string(), actor_id = %ch.actor_id,
+            creation_timestamp = ch.creation_timestamp, address = %ch.channel_info().to_string(),
+            "daily channel imported"
+        );
+        Ok(ch)
     }
 }
 
-fn key_nonce(psw: &str) -> (Vec<u8>, Vec<u8>) {
-    let key_hash = &hash_string(psw)[..32];
-    let nonce_hash = &hash_string(key_hash)[..24];
-    let key = key_hash.as_bytes();
-    let nonce = nonce_hash.as_bytes();
-    (key.to_vec(), nonce.to_vec())
+//
+// Rewrite a state exported before the envelope in the current format, without touching the tangle
+//
+pub (crate) fn upgrade_state(state: &str, state_psw: &str) -> errors::Result<String>{
+    DailyChannelState::decrypt(state, state_psw)?.encrypt(state_psw)
 }
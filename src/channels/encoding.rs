@@ -0,0 +1,85 @@
+use crate::channels::errors::{self, ChannelManagerError};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+//
+// Encoding of the payloads sent with DailyChannelManager::send.
+// JSON payloads are sent as plain JSON, so they are still read by the JSON consumers.
+// The other public payloads start with | 0xB4 | encoding |, the masked one is encoded in the same way without a header.
+// JSON text never starts with 0xB4, so the two can't be confused
+//
+const PACKET_MARKER: u8 = 0xB4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum PayloadEncoding{
+    #[default]
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+impl PayloadEncoding{
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> errors::Result<Vec<u8>>{
+        match self{
+            PayloadEncoding::Json => serde_json::to_vec(value).map_err(encoding_error),
+            PayloadEncoding::Cbor => {
+                let mut bytes = vec![];
+                ciborium::ser::into_writer(value, &mut bytes).map_err(encoding_error)?;
+                Ok(bytes)
+            },
+            // Structs are written as maps, so that the payload can be read without knowing its type
+            PayloadEncoding::MessagePack => rmp_serde::to_vec_named(value).map_err(encoding_error),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> errors::Result<T>{
+        match self{
+            PayloadEncoding::Json => serde_json::from_slice(bytes).map_err(encoding_error),
+            PayloadEncoding::Cbor => ciborium::de::from_reader(bytes).map_err(encoding_error),
+            PayloadEncoding::MessagePack => rmp_serde::from_slice(bytes).map_err(encoding_error),
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self{
+            PayloadEncoding::Json => 1,
+            PayloadEncoding::Cbor => 2,
+            PayloadEncoding::MessagePack => 3,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag{
+            1 => Some(PayloadEncoding::Json),
+            2 => Some(PayloadEncoding::Cbor),
+            3 => Some(PayloadEncoding::MessagePack),
+            _ => None
+        }
+    }
+}
+
+pub (crate) fn encode_public<T: Serialize + ?Sized>(encoding: PayloadEncoding, value: &T) -> errors::Result<Vec<u8>>{
+    if encoding == PayloadEncoding::Json{
+        return encoding.encode(value);
+    }
+    let mut bytes = vec![PACKET_MARKER, encoding.tag()];
+    bytes.extend(encoding.encode(value)?);
+    Ok(bytes)
+}
+
+//
+// Returns the encoding recorded in the public payload and the encoded value,
+// None for a payload without the header (a JSON packet)
+//
+pub (crate) fn split_public(bytes: &[u8]) -> errors::Result<Option<(PayloadEncoding, &[u8])>>{
+    match bytes{
+        [PACKET_MARKER, tag, payload @ ..] => PayloadEncoding::from_tag(*tag)
+            .map(|encoding| Some((encoding, payload)))
+            .ok_or_else(|| ChannelManagerError::Encoding(format!("Unknown payload encoding {}", tag))),
+        _ => Ok(None)
+    }
+}
+
+fn encoding_error<E: std::fmt::Display>(err: E) -> ChannelManagerError{
+    ChannelManagerError::Encoding(err.to_string())
+}
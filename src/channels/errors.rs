@@ -0,0 +1,105 @@
+use crate::transport::TransportError;
+use thiserror::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChannelLayer{
+    Root,
+    Category,
+    Actor,
+    Daily
+}
+
+impl fmt::Display for ChannelLayer{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layer = match self{
+            ChannelLayer::Root => "root",
+            ChannelLayer::Category => "category",
+            ChannelLayer::Actor => "actor",
+            ChannelLayer::Daily => "daily"
+        };
+        write!(f, "{}", layer)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ChannelManagerError{
+    #[error("Category {0} doesn't exist")]
+    CategoryNotFound(String),
+    #[error("Category {0} already exists")]
+    CategoryExists(String),
+    #[error("The tree has not been opened yet")]
+    TreeNotOpened,
+    #[error("The tree has already been opened")]
+    TreeAlreadyOpened,
+    #[error("Actor {0} doesn't exist yet")]
+    ActorNotFound(String),
+    #[error("Actor channel {0} already exists")]
+    ActorExists(String),
+    #[error("Daily channel in date {0} already exists")]
+    DailyChannelExists(String),
+    #[error("Daily channel in date {0} doesn't exist")]
+    DailyChannelNotFound(String),
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("Invalid date")]
+    InvalidDate,
+    #[error("Channel state is corrupted: {0}")]
+    StateCorrupted(String),
+    #[error("Channel state format {0} is not supported by this version")]
+    UnsupportedStateFormat(u16),
+    #[error("Cursor of channel {0} can't be used on this channel")]
+    InvalidCursor(String),
+    #[error("Message {0} not found")]
+    MessageNotFound(String),
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("A {found} message can't be sent on a {expected} channel")]
+    SchemaMismatch{ expected: String, found: String },
+    #[error("Payload encoding error: {0}")]
+    Encoding(String),
+    #[error("No password for {0} in the keystore")]
+    PasswordNotFound(String),
+    #[error("Keystore error: {0}")]
+    Keystore(String),
+    #[error("Impossible to import the {layer} channel {address}")]
+    ImportFailed{ layer: ChannelLayer, address: String, #[source] source: anyhow::Error },
+    #[error("Transport error: {0}")]
+    Transport(anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ChannelManagerError>;
+
+impl ChannelManagerError{
+    //
+    // A channel that can't be imported because of the password or of the node is reported as such,
+    // any other failure keeps its cause
+    //
+    pub (crate) fn import_failed(layer: ChannelLayer, address: String, err: anyhow::Error) -> Self {
+        match err.downcast_ref::<TransportError>(){
+            Some(_) => err.into(),
+            None => ChannelManagerError::ImportFailed{ layer, address, source: err }
+        }
+    }
+}
+
+impl From<anyhow::Error> for ChannelManagerError{
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<TransportError>(){
+            Some(TransportError::WrongPassword) => ChannelManagerError::WrongPassword,
+            _ => ChannelManagerError::Transport(err)
+        }
+    }
+}
+
+impl From<bincode::Error> for ChannelManagerError{
+    fn from(err: bincode::Error) -> Self {
+        ChannelManagerError::StateCorrupted(err.to_string())
+    }
+}
+
+impl From<base64::DecodeError> for ChannelManagerError{
+    fn from(err: base64::DecodeError) -> Self {
+        ChannelManagerError::StateCorrupted(err.to_string())
+    }
+}
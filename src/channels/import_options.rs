@@ -0,0 +1,111 @@
+use crate::channels::Category;
+use crate::channels::cache::CachePolicy;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+pub const DEFAULT_IMPORT_CONCURRENCY: usize = 8;
+
+//
+// Progress of the restoration of a category, reported every time one of its actors has been restored.
+// When actors are loaded on first use it is reported once, with all the announced actors deferred
+//
+#[derive(Debug, Clone)]
+pub struct ImportProgress{
+    pub category: Category,
+    pub restored: usize,
+    pub deferred: usize,
+    pub total: usize,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&ImportProgress) + Send + Sync>;
+
+//
+// Options used to restore the nested architecture from the tangle.
+// By default actors are loaded on first use; with `preload_actors` they are all restored during the import.
+// `concurrency` bounds the number of category and actor channels that are fetched at the same time across the whole tree
+//
+#[derive(Clone)]
+pub struct ImportOptions{
+    concurrency: usize,
+    preload_actors: bool,
+    cache_policy: CachePolicy,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl ImportOptions{
+    pub fn new() -> Self {
+        ImportOptions{ concurrency: DEFAULT_IMPORT_CONCURRENCY, preload_actors: false, cache_policy: CachePolicy::default(), on_progress: None }
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn preload_actors(mut self, preload: bool) -> Self {
+        self.preload_actors = preload;
+        self
+    }
+
+    pub fn cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = policy;
+        self
+    }
+
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+        where F: Fn(&ImportProgress) + Send + Sync + 'static
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn preloads_actors(&self) -> bool {
+        self.preload_actors
+    }
+
+    pub fn daily_channels_cache_policy(&self) -> CachePolicy {
+        self.cache_policy
+    }
+
+    pub (crate) fn report(&self, progress: ImportProgress){
+        if let Some(callback) = &self.on_progress{
+            callback(&progress);
+        }
+    }
+}
+
+impl Default for ImportOptions{
+    fn default() -> Self {
+        ImportOptions::new()
+    }
+}
+
+impl fmt::Debug for ImportOptions{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImportOptions")
+            .field("concurrency", &self.concurrency)
+            .field("preload_actors", &self.preload_actors)
+            .field("cache_policy", &self.cache_policy)
+            .field("on_progress", &self.on_progress.is_some())
+            .finish()
+    }
+}
+
+//
+// State of a single import: the options and the permits shared by all the channels fetched in parallel
+//
+pub (crate) struct ImportRun<'a>{
+    pub permits: Semaphore,
+    pub options: &'a ImportOptions,
+}
+
+impl<'a> ImportRun<'a>{
+    pub (crate) fn new(options: &'a ImportOptions) -> Self {
+        ImportRun{ permits: Semaphore::new(options.max_concurrency()), options }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::channels::Category;
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::channels::state_format::{seal_local_envelope, open_local_envelope};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+//
+// Source of the passwords used by the tree.
+// The tree password protects the root, category and actor channels, the daily password the daily channels of an actor.
+// Daily passwords are looked up for the actor, then for its category and finally as a default for every daily channel
+//
+pub trait Keystore: Send + Sync{
+    fn tree_password(&self) -> errors::Result<Zeroizing<String>>;
+    fn daily_password(&self, category: &Category, actor_id: &str) -> errors::Result<Zeroizing<String>>;
+}
+
+//
+// Keystore reading the passwords from environment variables:
+// <PREFIX>_TREE_PSW, <PREFIX>_DAILY_PSW_<CATEGORY>_<ACTOR>, <PREFIX>_DAILY_PSW_<CATEGORY> and <PREFIX>_DAILY_PSW.
+// Category and actor are upper case, every character that is not alphanumeric is replaced by '_'
+//
+pub const DEFAULT_ENV_PREFIX: &str = "BIOENPRO4TO";
+
+#[derive(Debug, Clone)]
+pub struct EnvKeystore{
+    prefix: String,
+}
+
+impl EnvKeystore{
+    pub fn new() -> Self {
+        EnvKeystore::with_prefix(DEFAULT_ENV_PREFIX)
+    }
+
+    pub fn with_prefix(prefix: &str) -> Self {
+        EnvKeystore { prefix: env_name(prefix) }
+    }
+
+    fn var(&self, name: &str) -> Option<Zeroizing<String>>{
+        std::env::var(format!("{}_{}", self.prefix, name)).ok().map(Zeroizing::new)
+    }
+}
+
+impl Default for EnvKeystore{
+    fn default() -> Self {
+        EnvKeystore::new()
+    }
+}
+
+impl Keystore for EnvKeystore{
+    fn tree_password(&self) -> errors::Result<Zeroizing<String>> {
+        self.var("TREE_PSW")
+            .ok_or_else(|| ChannelManagerError::PasswordNotFound(format!("{}_TREE_PSW", self.prefix)))
+    }
+
+    fn daily_password(&self, category: &Category, actor_id: &str) -> errors::Result<Zeroizing<String>> {
+        let category = env_name(category.id());
+        let actor_id = env_name(actor_id);
+        self.var(&format!("DAILY_PSW_{}_{}", category, actor_id))
+            .or_else(|| self.var(&format!("DAILY_PSW_{}", category)))
+            .or_else(|| self.var("DAILY_PSW"))
+            .ok_or_else(|| ChannelManagerError::PasswordNotFound(format!("{}_DAILY_PSW_{}_{}", self.prefix, category, actor_id)))
+    }
+}
+
+fn env_name(name: &str) -> String{
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+//
+// Keystore saved in a file encrypted with a master password, in the state envelope without the network.
+// The passwords are read when the file is opened and written only by save, the file is readable only by its owner
+//
+const KEYSTORE_MAGIC: [u8; 4] = *b"B4TK";
+pub const KEYSTORE_FORMAT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Secrets{
+    tree: Option<String>,
+    daily: Option<String>,
+    categories: HashMap<String, String>,
+    actors: HashMap<String, String>,
+}
+
+impl Drop for Secrets{
+    fn drop(&mut self) {
+        self.tree.zeroize();
+        self.daily.zeroize();
+        self.categories.values_mut().for_each(|psw| psw.zeroize());
+        self.actors.values_mut().for_each(|psw| psw.zeroize());
+    }
+}
+
+pub struct FileKeystore{
+    path: PathBuf,
+    master_psw: Zeroizing<String>,
+    secrets: Secrets,
+}
+
+impl FileKeystore{
+    //
+    // Empty keystore, nothing is written until save is called
+    //
+    pub fn create<P: AsRef<Path>>(path: P, master_psw: &str) -> Self {
+        FileKeystore { path: path.as_ref().to_path_buf(), master_psw: Zeroizing::new(master_psw.to_string()), secrets: Secrets::default() }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, master_psw: &str) -> errors::Result<Self>{
+        let bytes = std::fs::read(path.as_ref())
+            .map_err(|e| ChannelManagerError::Keystore(e.to_string()))?;
+        let (_, plain) = open_local_envelope(KEYSTORE_MAGIC, KEYSTORE_FORMAT_VERSION, &bytes, master_psw)?;
+        let secrets: Secrets = bincode::deserialize(&plain)?;
+        Ok(FileKeystore { path: path.as_ref().to_path_buf(), master_psw: Zeroizing::new(master_psw.to_string()), secrets })
+    }
+
+    pub fn save(&self) -> errors::Result<()>{
+        let plain = Zeroizing::new(bincode::serialize(&self.secrets)?);
+        let bytes = seal_local_envelope(KEYSTORE_MAGIC, KEYSTORE_FORMAT_VERSION, &plain, &self.master_psw)?;
+        write_private(&self.path, &bytes)
+            .map_err(|e| ChannelManagerError::Keystore(e.to_string()))
+    }
+
+    pub fn set_tree_password(&mut self, psw: &str){
+        self.secrets.tree.replace(psw.to_string()).zeroize();
+    }
+
+    pub fn set_daily_password(&mut self, psw: &str){
+        self.secrets.daily.replace(psw.to_string()).zeroize();
+    }
+
+    pub fn set_category_password(&mut self, category: &Category, psw: &str){
+        self.secrets.categories.insert(category.id().to_string(), psw.to_string()).zeroize();
+    }
+
+    pub fn set_actor_password(&mut self, category: &Category, actor_id: &str, psw: &str){
+        self.secrets.actors.insert(actor_key(category, actor_id), psw.to_string()).zeroize();
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Keystore for FileKeystore{
+    fn tree_password(&self) -> errors::Result<Zeroizing<String>> {
+        self.secrets.tree.clone()
+            .map(Zeroizing::new)
+            .ok_or_else(|| ChannelManagerError::PasswordNotFound("tree".to_string()))
+    }
+
+    fn daily_password(&self, category: &Category, actor_id: &str) -> errors::Result<Zeroizing<String>> {
+        let key = actor_key(category, actor_id);
+        self.secrets.actors.get(&key)
+            .or_else(|| self.secrets.categories.get(category.id()))
+            .or(self.secrets.daily.as_ref())
+            .map(|psw| Zeroizing::new(psw.clone()))
+            .ok_or(ChannelManagerError::PasswordNotFound(key))
+    }
+}
+
+fn actor_key(category: &Category, actor_id: &str) -> String{
+    format!("{}/{}", category.id(), actor_id.to_lowercase())
+}
+
+//
+// The file is written in a temporary file of the same directory, readable only by its owner, that replaces the old one
+// only when it is on the disk: a crash while saving leaves the previous keystore untouched
+//
+fn write_private(path: &Path, bytes: &[u8]) -> std::io::Result<()>{
+    let tmp = tmp_path(path);
+    match std::fs::remove_file(&tmp){
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if written.is_err(){
+        let _ = std::fs::remove_file(&tmp);
+        return written;
+    }
+    #[cfg(unix)]
+    std::fs::File::open(parent_dir(path))?.sync_all()?;
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> PathBuf{
+    let mut name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(".tmp");
+    parent_dir(path).join(name)
+}
+
+fn parent_dir(path: &Path) -> &Path{
+    match path.parent(){
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn file_keystore_round_trip(){
+        let path = std::env::temp_dir().join(format!("b4t_keystore_{}", std::process::id()));
+        let mut keystore = FileKeystore::create(&path, "master");
+        keystore.set_tree_password("tree");
+        keystore.set_category_password(&Category::trucks(), "trucks");
+        keystore.set_actor_password(&Category::trucks(), "Truck1", "truck1");
+        keystore.save().unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        keystore.set_daily_password("daily");
+        keystore.save().unwrap();
+        assert!(!tmp_path(&path).exists());
+        assert!(matches!(FileKeystore::open(&path, "wrong"), Err(ChannelManagerError::WrongPassword)));
+        let keystore = FileKeystore::open(&path, "master").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(keystore.tree_password().unwrap().as_str(), "tree");
+        assert_eq!(keystore.daily_password(&Category::trucks(), "truck1").unwrap().as_str(), "truck1");
+        assert_eq!(keystore.daily_password(&Category::trucks(), "truck2").unwrap().as_str(), "trucks");
+        assert_eq!(keystore.daily_password(&Category::scales(), "scale1").unwrap().as_str(), "daily");
+    }
+}
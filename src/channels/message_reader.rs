@@ -0,0 +1,166 @@
+use crate::channels::{ChannelInfo, NetworkConfig, PayloadEncoding};
+use crate::channels::encoding;
+use crate::channels::crypto;
+use crate::channels::errors;
+use crate::utils::current_time_secs;
+use crate::transport::{StreamsReader, Transport, TangleTransport};
+use iota_streams_lib::payload::payload_serializers::JsonPacket;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+//
+// Message read from a channel. The masked payload is available only if it could be read,
+// that is when it was sent in clear or when the reader has the key and nonce it was encrypted with
+//
+#[derive(Debug, Clone)]
+pub struct ChannelMessage{
+    msg_id: String,
+    link: String,
+    encoding: PayloadEncoding,
+    public: Value,
+    masked: Option<Value>,
+}
+
+impl ChannelMessage{
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    //
+    // Address of the message in the form channel_id:msg_id
+    //
+    pub fn link(&self) -> &str {
+        &self.link
+    }
+
+    //
+    // Encoding the message was sent with, the packets without the encoding header are JSON
+    //
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    pub fn public(&self) -> &Value {
+        &self.public
+    }
+
+    pub fn masked(&self) -> Option<&Value> {
+        self.masked.as_ref()
+    }
+
+    pub fn public_as<T: DeserializeOwned>(&self) -> errors::Result<T>{
+        from_value(&self.public)
+    }
+
+    pub fn masked_as<T: DeserializeOwned>(&self) -> errors::Result<Option<T>>{
+        self.masked.as_ref().map(from_value).transpose()
+    }
+}
+
+fn from_value<T: DeserializeOwned>(value: &Value) -> errors::Result<T>{
+    T::deserialize(value).map_err(|e| errors::ChannelManagerError::Encoding(e.to_string()))
+}
+
+pub struct MessageReader{
+    reader: Box<dyn StreamsReader>,
+    channel_id: String,
+    key_nonce: Option<([u8;32], [u8;24])>,
+    msgs: Vec<ChannelMessage>,
+    last_update: i64,
+}
+
+impl MessageReader{
+    pub async fn new(channel_info: &ChannelInfo, network: NetworkConfig) -> errors::Result<Self> {
+        MessageReader::new_with_transport(channel_info, Arc::new(TangleTransport::new(network))).await
+    }
+
+    pub async fn new_with_transport(channel_info: &ChannelInfo, transport: Arc<dyn Transport>) -> errors::Result<Self> {
+        MessageReader::new_with_key_nonce(channel_info, transport, None).await
+    }
+
+    //
+    // The key and nonce are used to decrypt the masked payloads
+    //
+    pub async fn new_with_key_nonce(channel_info: &ChannelInfo, transport: Arc<dyn Transport>,
+                                    key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<Self> {
+        let mut reader = transport.create_reader(channel_info.channel_id(), channel_info.announce_id());
+        reader.attach().await?;
+        let mut mr = MessageReader {
+            reader,
+            channel_id: channel_info.channel_id().to_string(),
+            key_nonce,
+            last_update: current_time_secs(),
+            msgs: vec![]
+        };
+        mr.read_messages().await?;
+        Ok(mr)
+    }
+
+    //
+    // The messages fetched are never fetched again, the ones that can't be parsed are skipped
+    // so that they don't hide the rest of the batch
+    //
+    pub async fn read_messages(&mut self) -> errors::Result<()>{
+        let msgs = self.reader.fetch_raw_msgs().await?;
+        if !msgs.is_empty(){
+            self.last_update = current_time_secs();
+        }
+        for (msg_id, p, m) in msgs{
+            match parse_message(&self.channel_id, &self.key_nonce, msg_id.clone(), &p, &m){
+                Ok(msg) => self.msgs.push(msg),
+                Err(err) => warn!(channel_id = %self.channel_id, msg_id = %msg_id, error = %err, "message skipped, it can't be parsed")
+            }
+        }
+        Ok(())
+    }
+
+    pub fn msgs(&self) -> &[ChannelMessage] {
+        &self.msgs
+    }
+
+    //
+    // Public payloads of every message read so far decoded as T
+    //
+    pub fn msgs_as<T: DeserializeOwned>(&self) -> errors::Result<Vec<T>>{
+        self.msgs.iter().map(|msg| msg.public_as()).collect()
+    }
+
+    pub fn last_updates_seconds_ago(&self) -> i64{
+        current_time_secs() - self.last_update
+    }
+}
+
+pub (crate) fn parse_message(channel_id: &str, key_nonce: &Option<([u8;32], [u8;24])>, msg_id: String, p: &[u8], m: &[u8]) -> errors::Result<ChannelMessage>{
+    let link = format!("{}:{}", channel_id, msg_id);
+    let (encoding, public, masked) = match encoding::split_public(p)?{
+        Some((encoding, payload)) => {
+            let masked = read_masked(key_nonce, m).and_then(|m| encoding.decode(&m).ok());
+            (encoding, encoding.decode(payload)?, masked)
+        },
+        None => {
+            // A masked payload that can't be decrypted leaves the public one readable, as for the other encodings
+            let (packet, masked_read) = match JsonPacket::from_streams_response(&p.to_vec(), &m.to_vec(), key_nonce){
+                Ok(packet) => (packet, !m.is_empty()),
+                Err(_) => (JsonPacket::from_streams_response(&p.to_vec(), &Vec::new(), &None)?, false)
+            };
+            let masked = if masked_read { packet.deserialize_masked().ok() } else { None };
+            (PayloadEncoding::Json, packet.deserialize_public()?, masked)
+        }
+    };
+    if masked.is_none() && !m.is_empty(){
+        debug!(link = %link, "masked payload not readable");
+    }
+    Ok(ChannelMessage{ msg_id, link, encoding, public, masked })
+}
+
+fn read_masked(key_nonce: &Option<([u8;32], [u8;24])>, m: &[u8]) -> Option<Vec<u8>>{
+    if m.is_empty(){
+        return None;
+    }
+    match key_nonce{
+        None => Some(m.to_vec()),
+        Some((key, nonce)) => crypto::decrypt(key, nonce, m, &[]).ok().map(|m| m.to_vec())
+    }
+}
@@ -1,60 +1,115 @@
 use serde::{Serialize, Deserialize};
-use iota_streams_lib::channels::{ChannelWriter, ChannelReader};
 
 pub mod root_channel;
 mod category_channel;
 mod actor_channel;
 pub use actor_channel::DailyChannelManager;
 pub mod daily_channel;
+mod network;
+pub mod errors;
+mod import_options;
+mod cache;
+mod crypto;
+mod state_format;
+pub use state_format::STATE_FORMAT_VERSION;
+mod backup;
+pub use backup::{ExportedBackup, BACKUP_FORMAT_VERSION};
+mod keystore;
+mod encoding;
+pub use encoding::PayloadEncoding;
+mod message_reader;
+pub use message_reader::{MessageReader, ChannelMessage};
+mod subscription;
+mod rolling_channel;
+pub use rolling_channel::RollingDailyChannel;
+pub use subscription::{Subscription, SubscriptionOptions, MessageCursor, DeliveredMessage, DEFAULT_POLL_INTERVAL, DEFAULT_MAX_BACKOFF};
+pub use keystore::{Keystore, EnvKeystore, FileKeystore, DEFAULT_ENV_PREFIX, KEYSTORE_FORMAT_VERSION};
+pub use cache::{CachePolicy, CacheStats, DEFAULT_CACHE_CAPACITY};
+pub use import_options::{ImportOptions, ImportProgress, ProgressCallback, DEFAULT_IMPORT_CONCURRENCY};
+pub use network::{NetworkConfig, PowMode};
 pub use category_channel::ActorChannelMsg as ActorChannelInfo;
 pub use actor_channel::DailyChannelMsg as DailyChannelInfo;
-use std::collections::HashMap;
-use serde_json::Value;
-use iota_streams_lib::payload::payload_serializers::JsonPacket;
-use crate::utils::current_time_secs;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
-pub enum Category{
-    Trucks,
-    Scales,
-    BioCells
+//
+// Category of actors of the plant (e.g. trucks, weighing scales, biocells).
+// Categories are identified by their id, the name and the label are only presentational
+//
+#[derive(Debug, Clone, Serialize, Deserialize, Eq)]
+pub struct Category{
+    id: String,
+    name: String,
+    explorer_label: String,
+}
+
+impl PartialEq for Category{
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Hash for Category{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 impl Category{
+    pub fn new(id: &str, name: &str, explorer_label: &str) -> Self {
+        Category { id: id.to_lowercase(), name: name.to_string(), explorer_label: explorer_label.to_string() }
+    }
+
+    pub fn trucks() -> Self{
+        Category::new("trucks", "Trucks", "Trucks")
+    }
+    pub fn scales() -> Self{
+        Category::new("weighing_scales", "Weighing Scales", "Scales")
+    }
+    pub fn biocells() -> Self{
+        Category::new("biocells", "BioCells", "BioCells")
+    }
+
+    //
+    // The categories of the BioEnPro4To project
+    //
+    pub fn defaults() -> Vec<Category>{
+        vec![Category::trucks(), Category::scales(), Category::biocells()]
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn explorer_label(&self) -> &str {
+        &self.explorer_label
+    }
+
     pub fn is_trucks(&self) -> bool{
-        self == &Category::Trucks
+        self == &Category::trucks()
     }
     pub fn is_scales(&self) -> bool{
-        self == &Category::Scales
+        self == &Category::scales()
     }
     pub fn is_biocells(&self) -> bool{
-        self == &Category::BioCells
-    }
-    /*pub fn equals_to(&self, other: &Category) -> bool{
-        match (self, other) {
-            (Category::Trucks, Category::Trucks) => true,
-            (Category::Scales, Category::Scales) => true,
-            (Category::BioCells, Category::BioCells) => true,
-            (_, _) => false
-        }
-    }*/
+        self == &Category::biocells()
+    }
 
     pub fn to_string(&self) -> String{
-        match self{
-            Category::Trucks => "trucks".to_string(),
-            Category::Scales => "weighing_scales".to_string(),
-            Category::BioCells => "biocells".to_string()
-        }
+        self.id.clone()
     }
 
+    //
+    // Only the default categories are known here, the ones added to a tree are resolved by RootChannel::category
+    //
     pub fn from_string(category: &str) -> Option<Category>{
+        Category::from_registered(category, &Category::defaults())
+    }
+
+    pub fn from_registered(category: &str, registered: &[Category]) -> Option<Category>{
         let category = category.to_lowercase();
-        match category.as_str(){
-            "trucks" => Some(Category::Trucks),
-            "weighing_scales" => Some(Category::Scales),
-            "biocells" => Some(Category::BioCells),
-            _ => None
-        }
+        registered.iter().find(|c| c.id == category).cloned()
     }
 }
 
@@ -75,65 +130,10 @@ impl ChannelInfo{
     pub fn announce_id(&self) -> &str {
         &self.announce_id
     }
-    pub fn explorer_url(&self) -> String{
-        format!("https://streams-chrysalis-explorer.netlify.app/channel/{}", self.to_string())
+    pub fn explorer_url(&self, network: &NetworkConfig) -> String{
+        network.channel_explorer_url(self)
     }
     pub fn to_string(&self) -> String{
         format!("{}:{}", self.channel_id, self.announce_id)
     }
 }
-
-pub struct MessageReader{
-    reader: ChannelReader,
-    msgs: Vec<HashMap<String, Value>>,
-    last_update: i64,
-}
-
-impl MessageReader{
-    pub async fn new(channel_info: &ChannelInfo, mainnet: bool) -> anyhow::Result<Self> {
-        let mut reader = create_reader(channel_info.channel_id(), channel_info.announce_id(), mainnet);
-        reader.attach().await?;
-        let mut mr = MessageReader { reader, last_update: current_time_secs(),msgs: vec![] };
-        mr.read_messages().await?;
-        Ok(mr)
-    }
-
-    pub async fn read_messages(&mut self) -> anyhow::Result<()>{
-        let msgs = self.reader.fetch_raw_msgs().await;
-        if msgs.len() > 0{
-            self.last_update = current_time_secs();
-        }
-        for (_, p, _) in msgs{
-            let packet = JsonPacket::from_streams_response(&p, &vec![], &None)?;
-            self.msgs.push(packet.deserialize_public()?);
-        }
-        Ok(())
-    }
-    
-    pub fn msgs(&self) -> &Vec<HashMap<String, Value>> {
-        &self.msgs
-    }
-
-    pub fn last_updates_seconds_ago(&self) -> i64{
-        current_time_secs() - self.last_update
-    }
-}
-
-fn create_channel(mainnet: bool) -> ChannelWriter{
-    ChannelWriter::builder()
-        .node(&node_url(mainnet))
-        .build()
-}
-
-fn create_reader(channel_id: &str, announce_id: &str, mainnet:bool) -> ChannelReader{
-    ChannelReader::builder()
-        .node(&node_url(mainnet))
-        .build(channel_id, announce_id)
-}
-
-pub fn node_url(mainnet: bool) -> String{
-    if mainnet{
-        return "https://chrysalis-nodes.iota.cafe/".to_string();
-    }
-    "https://api.lb-0.h.chrysalis-devnet.iota.cafe/".to_string()
-}
@@ -0,0 +1,138 @@
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use crate::channels::ChannelInfo;
+
+//
+// Where the proof of work of the messages sent is done, on this machine or by the node
+//
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum PowMode{
+    Local,
+    Remote
+}
+
+//
+// Network profile shared by every layer of the nested channel architecture
+//
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(from = "StoredNetworkConfig", into = "StoredNetworkConfig")]
+pub struct NetworkConfig{
+    node_urls: Vec<String>,
+    explorer_url: String,
+    permanode_url: Option<String>,
+    pow: PowMode,
+    request_timeout: Duration,
+    mainnet: bool,
+}
+
+impl NetworkConfig{
+    pub fn new(node_urls: Vec<String>, explorer_url: &str, mainnet: bool) -> Self {
+        NetworkConfig {
+            node_urls,
+            explorer_url: explorer_url.trim_end_matches('/').to_string(),
+            permanode_url: None,
+            pow: PowMode::Remote,
+            request_timeout: Duration::from_secs(30),
+            mainnet
+        }
+    }
+
+    pub fn mainnet() -> Self{
+        NetworkConfig::new(
+            vec!["https://chrysalis-nodes.iota.cafe/".to_string()],
+            "https://streams-chrysalis-explorer.netlify.app",
+            true
+        ).permanode("https://chrysalis-chronicle.iota.org/api/mainnet/")
+    }
+
+    pub fn devnet() -> Self{
+        NetworkConfig::new(
+            vec!["https://api.lb-0.h.chrysalis-devnet.iota.cafe/".to_string()],
+            "https://streams-chrysalis-explorer.netlify.app",
+            false
+        )
+    }
+
+    pub fn permanode(mut self, permanode_url: &str) -> Self{
+        self.permanode_url = Some(permanode_url.to_string());
+        self
+    }
+
+    pub fn pow(mut self, pow: PowMode) -> Self{
+        self.pow = pow;
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self{
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn node_urls(&self) -> &Vec<String> {
+        &self.node_urls
+    }
+    pub fn primary_node(&self) -> &str {
+        self.node_urls.first().map_or("", |url| url.as_str())
+    }
+    pub fn explorer_url(&self) -> &str {
+        &self.explorer_url
+    }
+    pub fn permanode_url(&self) -> Option<&str> {
+        self.permanode_url.as_deref()
+    }
+    pub fn pow_mode(&self) -> PowMode {
+        self.pow
+    }
+    pub fn timeout(&self) -> Duration {
+        self.request_timeout
+    }
+    pub fn is_mainnet(&self) -> bool {
+        self.mainnet
+    }
+
+    pub fn channel_explorer_url(&self, info: &ChannelInfo) -> String{
+        format!("{}/channel/{}?mainnet={}", self.explorer_url, info.to_string(), self.mainnet)
+    }
+}
+
+//
+// Layout of the profile inside the exported states and the backups,
+// the timeout is stored in whole seconds rounded up so that it can't become zero
+//
+#[derive(Serialize, Deserialize)]
+struct StoredNetworkConfig{
+    node_urls: Vec<String>,
+    explorer_url: String,
+    permanode_url: Option<String>,
+    pow: PowMode,
+    request_timeout_secs: u64,
+    mainnet: bool,
+}
+
+impl From<StoredNetworkConfig> for NetworkConfig{
+    fn from(stored: StoredNetworkConfig) -> Self {
+        NetworkConfig{
+            node_urls: stored.node_urls,
+            explorer_url: stored.explorer_url,
+            permanode_url: stored.permanode_url,
+            pow: stored.pow,
+            request_timeout: Duration::from_secs(stored.request_timeout_secs.max(1)),
+            mainnet: stored.mainnet
+        }
+    }
+}
+
+impl From<NetworkConfig> for StoredNetworkConfig{
+    fn from(network: NetworkConfig) -> Self {
+        let timeout = network.request_timeout;
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        StoredNetworkConfig{
+            node_urls: network.node_urls,
+            explorer_url: network.explorer_url,
+            permanode_url: network.permanode_url,
+            pow: network.pow,
+            request_timeout_secs: secs.max(1),
+            mainnet: network.mainnet
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::channels::{Category, ChannelInfo, DailyChannelManager, PayloadEncoding};
+use crate::channels::root_channel::RootChannel;
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::messages::CategoryMessage;
+use crate::utils::{current_time_secs, timestamp_to_day};
+use serde::Serialize;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+use tracing::info;
+
+//
+// Daily channel of an actor that follows the clock: every send goes to the channel of the current day,
+// when a send crosses midnight the channel of the new day is taken from the tree, creating it if needed.
+// Without a password the one of the actor is read from the keystore of the tree every time a channel is opened
+//
+pub struct RollingDailyChannel{
+    root: Arc<RootChannel>,
+    category: Category,
+    actor_id: String,
+    state_psw: Option<Zeroizing<String>>,
+    encoding: PayloadEncoding,
+    current: DailyChannelManager,
+}
+
+impl RollingDailyChannel{
+    pub async fn new(root: Arc<RootChannel>, category: Category, actor_id: &str, state_psw: &str) -> errors::Result<Self>{
+        RollingDailyChannel::open(root, category, actor_id, Some(Zeroizing::new(state_psw.to_string()))).await
+    }
+
+    pub async fn new_with_keystore(root: Arc<RootChannel>, category: Category, actor_id: &str) -> errors::Result<Self>{
+        RollingDailyChannel::open(root, category, actor_id, None).await
+    }
+
+    //
+    // Encoding used by send, it is kept across the days
+    //
+    pub fn with_encoding(mut self, encoding: PayloadEncoding) -> Self {
+        self.set_encoding(encoding);
+        self
+    }
+
+    pub fn set_encoding(&mut self, encoding: PayloadEncoding){
+        self.encoding = encoding;
+        self.current.set_encoding(encoding);
+    }
+
+    //
+    // Handle of the channel of the day the timestamp falls on in the timezone of the plant, that becomes the current one
+    //
+    pub async fn channel_for(&mut self, timestamp: i64) -> errors::Result<DailyChannelManager>{
+        let day = timestamp_to_day(timestamp, &self.root.timezone()).ok_or(ChannelManagerError::InvalidDate)?;
+        if self.current.creation_day()? != day{
+            let previous = self.current.channel_info();
+            self.current = fetch(&self.root, &self.category, &self.actor_id, &self.state_psw, timestamp).await?
+                .with_encoding(self.encoding);
+            info!(category = %self.category.to_string(), actor_id = %self.actor_id, from = %previous.to_string(),
+                  to = %self.current.channel_info().to_string(), "daily channel rolled over");
+        }
+        Ok(self.current.clone())
+    }
+
+    pub async fn send_raw_packet(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        self.channel_for(current_time_secs()).await?
+            .send_raw_packet(p_data, m_data, key_nonce).await
+    }
+
+    pub async fn send<P: Serialize, M: Serialize>(&mut self, public: &P, masked: &M, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        self.channel_for(current_time_secs()).await?
+            .send(public, masked, key_nonce).await
+    }
+
+    pub async fn send_typed<P: CategoryMessage, M: Serialize>(&mut self, public: &P, masked: &M, key_nonce: Option<([u8;32], [u8;24])>) -> errors::Result<String>{
+        self.channel_for(current_time_secs()).await?
+            .send_typed(public, masked, key_nonce).await
+    }
+
+    //
+    // Channel the last send went to, it may belong to a past day until the next send
+    //
+    pub fn current(&self) -> &DailyChannelManager {
+        &self.current
+    }
+
+    pub fn channel_info(&self) -> ChannelInfo{
+        self.current.channel_info()
+    }
+
+    pub fn category(&self) -> &Category {
+        &self.category
+    }
+
+    pub fn actor_id(&self) -> &str {
+        &self.actor_id
+    }
+}
+
+impl RollingDailyChannel{
+    async fn open(root: Arc<RootChannel>, category: Category, actor_id: &str, state_psw: Option<Zeroizing<String>>) -> errors::Result<Self>{
+        let current = fetch(&root, &category, actor_id, &state_psw, current_time_secs()).await?;
+        let encoding = current.encoding();
+        Ok(RollingDailyChannel{ root, category, actor_id: actor_id.to_string(), state_psw, encoding, current })
+    }
+}
+
+async fn fetch(root: &RootChannel, category: &Category, actor_id: &str, state_psw: &Option<Zeroizing<String>>, timestamp: i64) -> errors::Result<DailyChannelManager>{
+    match state_psw{
+        Some(psw) => root.daily_actor_channel_for(category.clone(), actor_id, psw, timestamp).await,
+        None => root.daily_channel_for(category.clone(), actor_id, timestamp).await
+    }
+}
@@ -1,29 +1,127 @@
 use crate::channels::category_channel::{CategoryChannel, ActorChannelMsg};
-use crate::channels::{Category, create_channel, ChannelInfo, create_reader, node_url};
-use iota_streams_lib::payload::payload_serializers::{JsonPacketBuilder, JsonPacket};
+use crate::channels::{Category, ChannelInfo, NetworkConfig, Keystore};
+use crate::channels::errors::{self, ChannelManagerError, ChannelLayer};
+use crate::channels::import_options::{ImportOptions, ImportRun};
+use crate::channels::cache::{DailyChannelCache, CachePolicy, CacheStats, CacheKey};
+use crate::channels::backup::{TreeBackup, WriterBackup, CategoryBackup, DailyChannelBackup, ExportedBackup, BACKUP_MAGIC, BACKUP_FORMAT_VERSION};
+use crate::channels::state_format::{seal_envelope, open_envelope, Opened};
+use crate::utils::{current_time_secs, timestamp_to_day, date_to_string, hash_string, utc, Tz};
+use chrono::NaiveDate;
 use serde::{Serialize, Deserialize};
 use crate::channels::actor_channel::{DailyChannelManager, DailyChannelMsg};
-use iota_streams_lib::channels::ChannelWriter;
-use std::sync::{Arc, Mutex};
+use crate::transport::{StreamsWriter, Transport, TangleTransport, send_public_packet, fetch_public_packets};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::time::Instant;
+use zeroize::Zeroizing;
+use tracing::{info, warn, instrument};
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CategoryChannelInfo{
+    pub category: Category,
+    pub address: ChannelInfo,
+}
+
+//
+// Category channels published on the root, kept in the order in which the categories were declared,
+// with the timezone of the plant. The trees published without it use UTC days
+//
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CategoryChannelsInfo{
-    pub trucks: ChannelInfo,
-    pub weighing_scales: ChannelInfo,
-    pub biocells: ChannelInfo,
+    pub categories: Vec<CategoryChannelInfo>,
+    #[serde(default = "utc")]
+    pub timezone: Tz,
+}
+
+impl CategoryChannelsInfo{
+    pub fn new(categories: Vec<(Category, ChannelInfo)>, timezone: Tz) -> Self{
+        let categories = categories.into_iter()
+            .map(|(category, address)| CategoryChannelInfo{ category, address })
+            .collect();
+        CategoryChannelsInfo{ categories, timezone }
+    }
+
+    pub fn get(&self, category_id: &str) -> Option<&CategoryChannelInfo>{
+        self.categories.iter().find(|info| info.category.id() == category_id.to_lowercase())
+    }
+
+    fn add(&mut self, info: CategoryChannelInfo){
+        if self.get(info.category.id()).is_none(){
+            self.categories.push(info);
+        }
+    }
+}
+
+//
+// Message published on the root every time a category is added after the tree has been opened
+//
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CategoryAddedMsg{
+    pub added: CategoryChannelInfo,
+}
+
+//
+// Format published by the roots created when the categories were fixed
+//
+#[derive(Deserialize)]
+struct LegacyCategoryChannelsInfo{
+    trucks: ChannelInfo,
+    weighing_scales: ChannelInfo,
+    biocells: ChannelInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RootMsg{
+    Categories(CategoryChannelsInfo),
+    CategoryAdded(CategoryAddedMsg),
+    Legacy(LegacyCategoryChannelsInfo),
 }
 
 impl CategoryChannelsInfo{
-    pub fn new(trucks: ChannelInfo, weighing_scale: ChannelInfo, biocell: ChannelInfo) -> Self{
-        CategoryChannelsInfo{ trucks, weighing_scales: weighing_scale, biocells: biocell }
+    //
+    // Rebuild the full category list replaying every message published on the root
+    //
+    fn replay(msgs: Vec<RootMsg>) -> Self{
+        let mut info = CategoryChannelsInfo{ categories: vec![], timezone: Tz::UTC };
+        for msg in msgs {
+            match msg{
+                RootMsg::Categories(categories) => {
+                    info.timezone = categories.timezone;
+                    categories.categories.into_iter().for_each(|c| info.add(c))
+                },
+                RootMsg::CategoryAdded(msg) => info.add(msg.added),
+                RootMsg::Legacy(legacy) => {
+                    info.add(CategoryChannelInfo{ category: Category::trucks(), address: legacy.trucks });
+                    info.add(CategoryChannelInfo{ category: Category::scales(), address: legacy.weighing_scales });
+                    info.add(CategoryChannelInfo{ category: Category::biocells(), address: legacy.biocells });
+                }
+            }
+        }
+        info
     }
 }
 
+//
+// Every category channel has its own async lock, so operations on different categories never wait on each other.
+// Locks are never held across calls into another layer apart from the one they protect.
+// With a keystore the passwords are read from it every time they are needed and never kept by the tree.
+// The writers on the tangle are imported with the password they were opened with, that is the one of the tree
+// until the password of the tree is rotated.
+// Daily channels are created, looked up and displayed on the days of the timezone of the plant
+//
 pub struct RootChannel{
-    root: ChannelWriter,
+    root: Mutex<Box<dyn StreamsWriter>>,
+    root_info: ChannelInfo,
     categories: Vec<(Arc<Mutex<CategoryChannel>>, Category)>,
-    psw: String,
-    mainnet: bool
+    opened: bool,
+    timezone: Tz,
+    psw: Zeroizing<String>,
+    tangle_psw: Option<Zeroizing<String>>,
+    keystore: Option<Arc<dyn Keystore>>,
+    transport: Arc<dyn Transport>,
+    daily_channels_cache: Arc<DailyChannelCache>,
 }
 
 
@@ -31,209 +129,680 @@ impl RootChannel{
     //
     // Build the Root Channel of the nested channel architecture of BioEnPro4To project
     //
-    pub fn new(mainnet: bool) -> Self {
-        let truck_category = (Arc::new(Mutex::new(CategoryChannel::new(Category::Trucks, mainnet))), Category::Trucks);
-        let weighing_scale_category = (Arc::new(Mutex::new(CategoryChannel::new(Category::Scales, mainnet))), Category::Scales);
-        let biocell_category = (Arc::new(Mutex::new(CategoryChannel::new(Category::BioCells, mainnet))), Category::BioCells);
-        let root = create_channel(mainnet);
-        RootChannel { root, categories: vec![truck_category, weighing_scale_category, biocell_category], psw: String::default(), mainnet }
+    pub fn new(network: NetworkConfig, categories: Vec<Category>) -> Self {
+        RootChannel::new_with_transport(Arc::new(TangleTransport::new(network)), categories)
+    }
+
+    //
+    // Build the Root Channel on top of a custom transport (e.g. an in-memory tangle)
+    //
+    pub fn new_with_transport(transport: Arc<dyn Transport>, categories: Vec<Category>) -> Self {
+        RootChannel::new_with_timezone(transport, categories, Tz::UTC)
+    }
+
+    //
+    // Build the Root Channel of a plant in the given timezone, e.g. `"Europe/Rome".parse::<Tz>()`.
+    // The timezone is published on the root and can't be changed afterwards
+    //
+    pub fn new_with_timezone(transport: Arc<dyn Transport>, categories: Vec<Category>, timezone: Tz) -> Self {
+        let cache = Arc::new(DailyChannelCache::new(CachePolicy::default()));
+        let mut category_channels: Vec<(Arc<Mutex<CategoryChannel>>, Category)> = vec![];
+        for category in categories {
+            if category_channels.iter().any(|(_, c)| c == &category){
+                continue;
+            }
+            let channel = CategoryChannel::new(category.clone(), timezone, transport.clone(), cache.clone());
+            category_channels.push((Arc::new(Mutex::new(channel)), category));
+        }
+        let root = transport.create_writer();
+        let root_info = writer_info(root.as_ref());
+        RootChannel { root: Mutex::new(root), root_info, categories: category_channels, opened: false, timezone, psw: Zeroizing::new(String::new()), tangle_psw: None, keystore: None, transport, daily_channels_cache: cache }
     }
 
     //
     // Restore the entire nested architecture giving the address of the root channel and the password previously used for the encryption of the state
     //
-    pub async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, mainnet: bool) -> anyhow::Result<Self>{
-        let node_url = node_url(mainnet);
-        println!("Importing tree");
-        let root = ChannelWriter::import_from_tangle(
-            channel_id,
-            announce_id,
-            state_psw,
-            Some(node_url.as_str()),
-            None
-        ).await?;
-        println!("  Root imported");
-
-        let categories_info = RootChannel::read_categories_channels_info(channel_id, announce_id, mainnet).await?;
-        let categories = RootChannel::import_categories(categories_info, state_psw, mainnet).await?;
+    pub async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, network: NetworkConfig) -> errors::Result<Self>{
+        RootChannel::import_from_tangle_with_transport(channel_id, announce_id, state_psw, Arc::new(TangleTransport::new(network))).await
+    }
+
+    pub async fn import_from_tangle_with_transport(channel_id: &str, announce_id: &str, state_psw: &str, transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        RootChannel::import_from_tangle_with_options(channel_id, announce_id, state_psw, transport, ImportOptions::default()).await
+    }
+
+    //
+    // Restore the tree importing categories and actors in parallel, at most `options.max_concurrency()` channels at a time
+    //
+    #[instrument(name = "import_tree", skip_all, fields(address = %format!("{}:{}", channel_id, announce_id), concurrency = options.max_concurrency()))]
+    pub async fn import_from_tangle_with_options(channel_id: &str, announce_id: &str, state_psw: &str, transport: Arc<dyn Transport>,
+                                                 options: ImportOptions) -> errors::Result<Self>{
+        let start = Instant::now();
+        let root = transport.import_from_tangle(channel_id, announce_id, state_psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Root, format!("{}:{}", channel_id, announce_id), err))?;
+        info!(elapsed_ms = start.elapsed().as_millis() as u64, "root imported");
+
+        let categories_info = RootChannel::read_categories_channels_info(channel_id, announce_id, transport.as_ref()).await?;
+        let timezone = categories_info.timezone;
+        let cache = Arc::new(DailyChannelCache::new(options.daily_channels_cache_policy()));
+        let categories = RootChannel::import_categories(categories_info, state_psw, transport.clone(), cache.clone(), &options).await?;
+        info!(elapsed_ms = start.elapsed().as_millis() as u64, "tree imported");
 
         Ok(RootChannel{
-            root,
-            categories: vec![
-                (Arc::new(Mutex::new(categories.0)), Category::Trucks),
-                (Arc::new(Mutex::new(categories.1)), Category::Scales),
-                (Arc::new(Mutex::new(categories.2)), Category::BioCells)
-            ],
-            psw: state_psw.to_string(),
-            mainnet
+            root_info: writer_info(root.as_ref()),
+            root: Mutex::new(root),
+            categories: categories.into_iter()
+                .map(|cat| {
+                    let category = cat.category().clone();
+                    (Arc::new(Mutex::new(cat)), category)
+                })
+                .collect(),
+            opened: true,
+            timezone,
+            psw: Zeroizing::new(state_psw.to_string()),
+            tangle_psw: None,
+            keystore: None,
+            transport,
+            daily_channels_cache: cache,
         })
     }
 
     //
-    // Initialize and opens the first two layers of the nested architecture
+    // Restore the tree with the password of the keystore, that is then used by every other operation
+    //
+    pub async fn import_from_tangle_with_keystore(channel_id: &str, announce_id: &str, keystore: Arc<dyn Keystore>,
+                                                  transport: Arc<dyn Transport>) -> errors::Result<Self>{
+        let state_psw = keystore.tree_password()?;
+        let mut root = RootChannel::import_from_tangle_with_transport(channel_id, announce_id, &state_psw, transport).await?;
+        root.set_keystore(keystore);
+        Ok(root)
+    }
+
+    //
+    // From now on the passwords are read from the keystore, the passwords kept by the tree are dropped.
+    // The tree password of the keystore must be the one the writers on the tangle were opened with
+    //
+    pub fn set_keystore(&mut self, keystore: Arc<dyn Keystore>){
+        self.psw = Zeroizing::new(String::new());
+        self.tangle_psw = None;
+        self.keystore = Some(keystore);
+    }
+
+    //
+    // Initialize and opens the first two layers of the nested architecture, with the categories added so far.
+    // A tree can be opened only once, the ones imported or restored are already opened
     //
-    pub async fn open(&mut self, channel_psw: &str) -> anyhow::Result<ChannelInfo> {
+    pub async fn open(&mut self, channel_psw: &str) -> errors::Result<ChannelInfo> {
+        let root_info = self.open_tree(channel_psw).await?;
+        self.psw = Zeroizing::new(channel_psw.to_string());
+        Ok(root_info)
+    }
+
+    pub async fn open_with_keystore(&mut self) -> errors::Result<ChannelInfo> {
+        let channel_psw = self.keystore()?.tree_password()?;
+        self.open_tree(&channel_psw).await
+    }
+
+    #[instrument(name = "open_tree", skip_all)]
+    async fn open_tree(&mut self, channel_psw: &str) -> errors::Result<ChannelInfo> {
+        if self.opened{
+            return Err(ChannelManagerError::TreeAlreadyOpened);
+        }
+        let start = Instant::now();
         // Opening Channels Category Info
-        println!("Initializing channels...");
-        self.categories[0].0.lock().unwrap().open(channel_psw).await?;
-        println!("  Trucks tree initialized");
-        self.categories[1].0.lock().unwrap().open(channel_psw).await?;
-        println!("  Scales tree initialized");
-        self.categories[2].0.lock().unwrap().open(channel_psw).await?;
-        println!("  Biocells tree initialized");
+        for (category, cat_type) in self.categories.iter() {
+            let info = category.lock().await.open(channel_psw).await?;
+            info!(category = %cat_type.to_string(), address = %info.to_string(), "category channel opened");
+        }
         // Opening the root channel
-        let root_info = self.root.open_and_save(channel_psw).await?;
+        let root_info = self.root.get_mut().open_and_save(channel_psw).await?;
+        let root_info = ChannelInfo::new(root_info.0, root_info.1);
+        self.root_info = root_info.clone();
         self.init_categories().await?;
-        self.psw = channel_psw.to_string();
-        Ok(ChannelInfo::new(root_info.0, root_info.1))
+        self.opened = true;
+        info!(address = %root_info.to_string(), elapsed_ms = start.elapsed().as_millis() as u64, "tree opened");
+        Ok(root_info)
     }
 
 
 
+    //
+    // Open a new category channel on an already opened tree and publish it on the root.
+    // The categories of a tree not opened yet are the ones it is built with
+    //
+    #[instrument(name = "add_category", skip_all, fields(category = %category.to_string()))]
+    pub async fn add_category(&mut self, category: Category) -> errors::Result<ChannelInfo>{
+        if self.categories.iter().any(|(_, cat)| cat == &category){
+            return Err(ChannelManagerError::CategoryExists(category.to_string()));
+        }
+        if !self.opened{
+            return Err(ChannelManagerError::TreeNotOpened);
+        }
+        let psw = self.tangle_psw()?;
+        let mut channel = CategoryChannel::new(category.clone(), self.timezone, self.transport.clone(), self.daily_channels_cache.clone());
+        let info = channel.open(&psw).await?;
+        let msg = CategoryAddedMsg{ added: CategoryChannelInfo{ category: category.clone(), address: info.clone() } };
+        send_public_packet(self.root.get_mut().as_mut(), &msg).await?;
+        self.categories.push((Arc::new(Mutex::new(channel)), category));
+        info!(address = %info.to_string(), "category added");
+        Ok(info)
+    }
+
     //
     // Create the daily channel for a given actor of a certain category for the specified date
     //
 
-    pub async fn new_daily_actor_channel(&mut self, category: Category, actor_id: &str, state_psw: &str,
-                                         day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
-        println!("Trying creating daily channel: ({}, {}, {:02}/{:02}/{})", category.to_string(), actor_id, day, month, year);
-        let category = &self.categories.iter_mut().find(|cat| category == cat.1).unwrap().0;
-        let res = category.lock().unwrap().new_daily_actor_channel(actor_id, &self.psw, state_psw, day, month, year).await;
+    #[instrument(name = "new_daily_channel", skip_all, fields(category = %category.to_string(), actor_id = %actor_id, date = %date_to_string(&date)))]
+    pub async fn new_daily_actor_channel(&self, category: Category, actor_id: &str, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let start = Instant::now();
+        let root_psw = self.tangle_psw()?;
+        let category = self.category_channel(&category)?;
+        let res = category.lock().await.new_daily_actor_channel(actor_id, &root_psw, state_psw, date).await;
+        match res{
+            Ok(res) => {
+                info!(address = %res.channel_info().to_string(), elapsed_ms = start.elapsed().as_millis() as u64, "daily channel created");
+                Ok(res)
+            },
+            Err(err) => {
+                warn!(error = %err, "daily channel creation failed");
+                Err(err)
+            }
+        }
+    }
+
+    #[instrument(name = "get_daily_channel", skip_all, fields(category = %category.to_string(), actor_id = %actor_id, date = %date_to_string(&date)))]
+    pub async fn get_daily_actor_channel(&self, category: Category, actor_id: &str, state_psw: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let start = Instant::now();
+        let root_psw = self.tangle_psw()?;
+        let category = self.category_channel(&category)?;
+        let res = category.lock().await.get_daily_actor_channel(actor_id, &root_psw, state_psw, date).await;
         match res{
             Ok(res) => {
-                println!("  Creation Complete");
+                info!(address = %res.channel_info().to_string(), elapsed_ms = start.elapsed().as_millis() as u64, "daily channel retrieved");
                 Ok(res)
             },
             Err(err) => {
-                eprintln!("  {}", err.to_string());
+                warn!(error = %err, "daily channel retrieval failed");
                 Err(err)
             }
         }
     }
 
-    pub async fn get_daily_actor_channel(&mut self, category: Category, actor_id: &str, state_psw: &str,
-                                         day: u16, month: u16, year: u16) -> anyhow::Result<DailyChannelManager>{
-        println!("Getting daily channel: ({}, {}, {:02}/{:02}/{})", category.to_string(), actor_id, day, month, year);
-        let category = &self.categories.iter_mut().find(|cat| category == cat.1).unwrap().0;
-        let res = category.lock().unwrap().get_daily_actor_channel(actor_id, state_psw, day, month, year).await;
+    //
+    // Get the daily channel of the day the timestamp falls on, creating the actor and the channel if they don't exist yet.
+    // Calling it again for the same day always returns the same channel. A timestamp out of the range of the dates
+    // gives InvalidDate before anything is created
+    //
+    #[instrument(name = "daily_channel_for", skip_all, fields(category = %category.to_string(), actor_id = %actor_id, timestamp = timestamp))]
+    pub async fn daily_actor_channel_for(&self, category: Category, actor_id: &str, state_psw: &str, timestamp: i64) -> errors::Result<DailyChannelManager>{
+        let start = Instant::now();
+        let date = timestamp_to_day(timestamp, &self.timezone).ok_or(ChannelManagerError::InvalidDate)?;
+        let root_psw = self.tangle_psw()?;
+        let category = self.category_channel(&category)?;
+        let res = category.lock().await.daily_actor_channel_for(actor_id, &root_psw, state_psw, date).await;
         match res{
             Ok(res) => {
-                println!("  Getting Complete");
+                info!(address = %res.channel_info().to_string(), elapsed_ms = start.elapsed().as_millis() as u64, "daily channel ready");
                 Ok(res)
             },
             Err(err) => {
-                eprintln!("{}", err.to_string());
+                warn!(error = %err, "daily channel not available");
                 Err(err)
             }
         }
     }
 
-    pub async fn serialize_daily_actor_channel(&mut self, category: Category, actor_id: &str, state_psw: &str,
-                                               day: u16, month: u16, year: u16) -> anyhow::Result<String>{
-        println!("Serializing daily channel: ({}, {}, {:02}/{:02}/{})", category.to_string(), actor_id, day, month, year);
-        let category = &self.categories.iter_mut().find(|cat| category == cat.1).unwrap().0;
-        let res = category.lock().unwrap().serialize_daily_actor_channel(actor_id, state_psw, day, month, year).await;
-        println!("  Serializing complete");
+    #[instrument(name = "serialize_daily_channel", skip_all, fields(category = %category.to_string(), actor_id = %actor_id, date = %date_to_string(&date)))]
+    pub async fn serialize_daily_actor_channel(&self, category: Category, actor_id: &str, state_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        let start = Instant::now();
+        let root_psw = self.tangle_psw()?;
+        let category = self.category_channel(&category)?;
+        let res = category.lock().await.serialize_daily_actor_channel(actor_id, &root_psw, state_psw, date).await;
+        match &res{
+            Ok(_) => info!(elapsed_ms = start.elapsed().as_millis() as u64, "daily channel serialized"),
+            Err(err) => warn!(error = %err, "daily channel serialization failed")
+        }
         res
     }
 
+    //
+    // Create, get or export a daily channel with the password of the actor taken from the keystore
+    //
+    pub async fn new_daily_channel(&self, category: Category, actor_id: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let state_psw = self.keystore()?.daily_password(&category, actor_id)?;
+        self.new_daily_actor_channel(category, actor_id, &state_psw, date).await
+    }
+
+    pub async fn get_daily_channel(&self, category: Category, actor_id: &str, date: NaiveDate) -> errors::Result<DailyChannelManager>{
+        let state_psw = self.keystore()?.daily_password(&category, actor_id)?;
+        self.get_daily_actor_channel(category, actor_id, &state_psw, date).await
+    }
+
+    pub async fn daily_channel_for(&self, category: Category, actor_id: &str, timestamp: i64) -> errors::Result<DailyChannelManager>{
+        let state_psw = self.keystore()?.daily_password(&category, actor_id)?;
+        self.daily_actor_channel_for(category, actor_id, &state_psw, timestamp).await
+    }
+
+    pub async fn serialize_daily_channel(&self, category: Category, actor_id: &str, date: NaiveDate) -> errors::Result<String>{
+        let state_psw = self.keystore()?.daily_password(&category, actor_id)?;
+        self.serialize_daily_actor_channel(category, actor_id, &state_psw, date).await
+    }
+
+    //
+    // Change the password of the tree: from now on the old one is refused and the returned backup is encrypted with the new one.
+    // The writers on the tangle keep the password they were opened with, the tree keeps it to import them
+    // and saves it in the backups. A tree imported again from the tangle still needs the old password
+    //
+    #[instrument(name = "rotate_password", skip_all)]
+    pub async fn rotate_password(&mut self, old_psw: &str, new_psw: &str) -> errors::Result<ExportedBackup>{
+        if old_psw != self.tree_psw()?.as_str(){
+            return Err(ChannelManagerError::WrongPassword);
+        }
+        self.tangle_psw = Some(self.tangle_psw()?);
+        self.psw = Zeroizing::new(new_psw.to_string());
+        let backup = self.backup(new_psw).await?;
+        info!("tree password rotated");
+        Ok(backup)
+    }
+
+    //
+    // Change the password of a daily channel: the returned state is exported with the new password,
+    // the channel is cached under it and the old one is refused by the tree, by its backups as well.
+    // The writer on the tangle keeps the password it was opened with, that the tree keeps to import the channel
+    //
+    #[instrument(name = "rotate_daily_channel_password", skip_all, fields(category = %category.to_string(), actor_id = %actor_id, date = %date_to_string(&date)))]
+    pub async fn rotate_daily_channel_password(&self, category: Category, actor_id: &str, old_psw: &str, new_psw: &str, date: NaiveDate) -> errors::Result<String>{
+        let root_psw = self.tangle_psw()?;
+        let category = self.category_channel(&category)?;
+        let state = category.lock().await.rotate_daily_channel_password(actor_id, &root_psw, old_psw, new_psw, date).await?;
+        info!("daily channel password rotated");
+        Ok(state)
+    }
+
     //
     // Returns the channel info of the root channel
     //
     pub fn channel_info(&self) -> ChannelInfo{
-        let info = self.root.channel_address();
-        ChannelInfo::new(info.0, info.1)
+        self.root_info.clone()
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
     }
 
     //
     // Tells if the root channel is attached to the mainnet or not
     //
     pub fn is_mainnet(&self) -> bool{
-        self.mainnet
+        self.transport.network().is_mainnet()
     }
 
     //
-    // Print all the architecture in a hierarchical view
+    // Returns the network profile shared by the whole tree
     //
-    pub fn print_nested_channel_info(&self){
+    pub fn network(&self) -> &NetworkConfig{
+        self.transport.network()
+    }
+
+    //
+    // Print all the architecture in a hierarchical view.
+    // This is the only API that writes on stdout, progress is reported with tracing events
+    //
+    pub async fn print_nested_channel_info(&self){
         let info = self.channel_info();
-        println!("\nRoot = https://streams-chrysalis-explorer.netlify.app/channel/{}:{}?mainnet={}", info.channel_id, info.announce_id, self.mainnet);
+        println!("\nRoot = {}", info.explorer_url(self.network()));
 
-        self.categories[0].0.lock().unwrap().print_nested_channel_info();
-        self.categories[1].0.lock().unwrap().print_nested_channel_info();
-        self.categories[2].0.lock().unwrap().print_nested_channel_info();
+        let last = self.categories.len().saturating_sub(1);
+        for (i, (cat, _)) in self.categories.iter().enumerate() {
+            cat.lock().await.print_nested_channel_info(i == last);
+        }
         println!();
     }
 }
 
+fn writer_info(writer: &dyn StreamsWriter) -> ChannelInfo{
+    let info = writer.channel_address();
+    ChannelInfo::new(info.0, info.1)
+}
+
 impl RootChannel {
-    async fn init_categories(&mut self) -> anyhow::Result<()>{
-        println!("Initializing tree messages...");
-        let truck_info = self.categories[0].0.lock().unwrap().channel_info();
-        let scale_info = self.categories[1].0.lock().unwrap().channel_info();
-        let biocell_info = self.categories[2].0.lock().unwrap().channel_info();
+    fn keystore(&self) -> errors::Result<&dyn Keystore>{
+        self.keystore.as_deref()
+            .ok_or_else(|| ChannelManagerError::Keystore("No keystore configured".to_string()))
+    }
+
+    //
+    // After a rotation the password of the tree is the one kept by the tree, even with a keystore
+    //
+    fn tree_psw(&self) -> errors::Result<Zeroizing<String>>{
+        match (&self.keystore, &self.tangle_psw){
+            (Some(keystore), None) => keystore.tree_password(),
+            _ => Ok(self.psw.clone())
+        }
+    }
+
+    fn tangle_psw(&self) -> errors::Result<Zeroizing<String>>{
+        match &self.tangle_psw{
+            Some(psw) => Ok(psw.clone()),
+            None => self.tree_psw()
+        }
+    }
+
+    fn category_channel(&self, category: &Category) -> errors::Result<Arc<Mutex<CategoryChannel>>>{
+        self.categories.iter()
+            .find(|(_, cat)| cat == category)
+            .map(|(ch, _)| ch.clone())
+            .ok_or_else(|| ChannelManagerError::CategoryNotFound(category.to_string()))
+    }
+
+    async fn init_categories(&mut self) -> errors::Result<()>{
+        let mut categories = vec![];
+        for (ch, category) in self.categories.iter() {
+            categories.push((category.clone(), ch.lock().await.channel_info()));
+        }
 
         //Creating MSG to send containing the info for every category channel
-        let categories_info = CategoryChannelsInfo::new(truck_info, scale_info, biocell_info);
-        let packet = JsonPacketBuilder::new()
-            .public(&categories_info)?
-            .build();
-        self.root.send_signed_packet(&packet).await?;
-        println!("  Initial messages sent");
+        let categories_info = CategoryChannelsInfo::new(categories, self.timezone);
+        send_public_packet(self.root.get_mut().as_mut(), &categories_info).await?;
+        info!("category channels published on root");
         Ok(())
     }
 
-    async fn read_categories_channels_info(channel_id: &str, announce_id: &str, mainnet: bool) -> anyhow::Result<CategoryChannelsInfo>{
-        let mut reader = create_reader(channel_id, announce_id, mainnet);
+    async fn read_categories_channels_info(channel_id: &str, announce_id: &str, transport: &dyn Transport) -> errors::Result<CategoryChannelsInfo>{
+        let mut reader = transport.create_reader(channel_id, announce_id);
         reader.attach().await?;
-        let mut msgs: Vec<(String, JsonPacket)> = reader.fetch_parsed_msgs(&None).await?;
-        match msgs.pop(){
-            None => Err(anyhow::Error::msg("Error during parsing categories channels")),
-            Some((_, packet)) => packet.deserialize_public()
-        }
-    }
-
-    async fn import_categories(categories_info: CategoryChannelsInfo, state_psw: &str, mainnet: bool) -> anyhow::Result<(CategoryChannel, CategoryChannel, CategoryChannel)>{
-        println!("Importing categories...");
-        let truck_category = CategoryChannel::import_from_tangle(
-            &categories_info.trucks.channel_id,
-            &categories_info.trucks.announce_id,
-            state_psw,
-            Category::Trucks,
-            mainnet
-        ).await?;
-        println!("  Trucks imported");
-        let weighing_scale_category = CategoryChannel::import_from_tangle(
-            &categories_info.weighing_scales.channel_id,
-            &categories_info.weighing_scales.announce_id,
-            state_psw,
-            Category::Scales,
-            mainnet
-        ).await?;
-        println!("  Scales imported");
-        let biocell_category = CategoryChannel::import_from_tangle(
-            &categories_info.biocells.channel_id,
-            &categories_info.biocells.announce_id,
-            state_psw,
-            Category::BioCells,
-            mainnet
-        ).await?;
-        println!("  Biocells imported");
-        Ok((truck_category, weighing_scale_category, biocell_category))
+        let msgs = fetch_public_packets::<RootMsg>(reader.as_mut()).await?;
+        if msgs.is_empty(){
+            let err = anyhow::Error::msg("No category announced on the root");
+            return Err(ChannelManagerError::import_failed(ChannelLayer::Root, format!("{}:{}", channel_id, announce_id), err));
+        }
+        Ok(CategoryChannelsInfo::replay(msgs.into_iter().map(|(_, msg)| msg).collect()))
+    }
+
+    async fn import_categories(categories_info: CategoryChannelsInfo, state_psw: &str, transport: Arc<dyn Transport>,
+                               cache: Arc<DailyChannelCache>, options: &ImportOptions) -> errors::Result<Vec<CategoryChannel>>{
+        let run = ImportRun::new(options);
+        let timezone = categories_info.timezone;
+        stream::iter(categories_info.categories.iter())
+            .map(|info| {
+                let (transport, cache, run) = (transport.clone(), cache.clone(), &run);
+                async move {
+                    let category = CategoryChannel::import_from_tangle(
+                        info.address.channel_id(),
+                        info.address.announce_id(),
+                        state_psw,
+                        info.category.clone(),
+                        timezone,
+                        transport,
+                        cache,
+                        run
+                    ).await?;
+                    info!(category = %info.category.to_string(), address = %info.address.to_string(), "category imported");
+                    Ok::<_, ChannelManagerError>(category)
+                }
+            })
+            .buffered(options.max_concurrency())
+            .try_collect()
+            .await
+    }
+}
+
+// Backup and restore of the whole tree
+impl RootChannel{
+    //
+    // Export the states of the root, category and actor writers together with their announcements,
+    // and the ones of the daily channels. The backup is encrypted with the password of the tree.
+    // The daily channels that are not in the cache are imported from the tangle with the passwords of the keystore,
+    // without a keystore they are left out and listed in the returned backup
+    //
+    #[instrument(name = "export_backup", skip_all)]
+    pub async fn export_backup(&self, psw: &str) -> errors::Result<ExportedBackup>{
+        if psw != self.tree_psw()?.as_str(){
+            return Err(ChannelManagerError::WrongPassword);
+        }
+        self.backup(psw).await
+    }
+
+    async fn backup(&self, backup_psw: &str) -> errors::Result<ExportedBackup>{
+        let tangle_psw = self.tangle_psw()?;
+        let options = ImportOptions::default();
+        let run = ImportRun::new(&options);
+        let root = WriterBackup{ address: self.channel_info(), state: self.root.lock().await.export_to_bytes(backup_psw)? };
+        let mut categories = vec![];
+        for (category, _) in self.categories.iter() {
+            categories.push(category.lock().await.backup(&tangle_psw, backup_psw, &run).await?);
+        }
+        let mut daily_channels = vec![];
+        for (key, manager) in self.daily_channels_cache.entries().await {
+            daily_channels.push(manager.backup(&key, backup_psw).await?);
+        }
+        let (not_cached, missing_daily_channels) = self.backup_daily_channels_not_cached(&categories, &daily_channels, backup_psw, &run).await?;
+        daily_channels.extend(not_cached);
+
+        let backup = TreeBackup{
+            root,
+            categories,
+            created_at: current_time_secs(),
+            timezone: self.timezone,
+            daily_channels,
+            tangle_psw: tangle_psw.to_string(),
+        };
+        let plain = Zeroizing::new(bincode::serialize(&backup)?);
+        let bytes = seal_envelope(BACKUP_MAGIC, BACKUP_FORMAT_VERSION, self.network(), &plain, backup_psw)?;
+        if !missing_daily_channels.is_empty(){
+            warn!(missing = missing_daily_channels.len(), "daily channels not in the cache and without a password in the keystore left out of the backup");
+        }
+        info!(categories = backup.categories.len(), daily_channels = backup.daily_channels.len(), bytes = bytes.len(), "backup exported");
+        Ok(ExportedBackup{ bytes, missing_daily_channels })
+    }
+
+    //
+    // Daily channels announced by the actors of the backup that are not in the cache, imported in parallel.
+    // Returns the ones without a password, or whose rotated password is not the one of the keystore, as well
+    //
+    async fn backup_daily_channels_not_cached(&self, categories: &[CategoryBackup], cached: &[DailyChannelBackup], backup_psw: &str,
+                                              run: &ImportRun<'_>) -> errors::Result<(Vec<DailyChannelBackup>, Vec<DailyChannelMsg>)>{
+        let mut to_import = vec![];
+        let mut missing = vec![];
+        for category in categories {
+            for actor in category.actor_channels.iter() {
+                for msg in actor.daily_channels.iter() {
+                    if cached.iter().any(|d| d.writer.address.to_string() == msg.address().to_string()){
+                        continue;
+                    }
+                    let psw = match self.keystore.as_deref().map(|keystore| keystore.daily_password(&category.category, &actor.actor_id)){
+                        Some(Ok(psw)) => psw,
+                        Some(Err(ChannelManagerError::PasswordNotFound(_))) | None => {
+                            missing.push(msg.clone());
+                            continue;
+                        },
+                        Some(Err(err)) => return Err(err),
+                    };
+                    // A rotated channel is imported with the password it was opened with, if the keystore has the new one
+                    let tangle_psw = match actor.rotated_passwords.iter().find(|r| r.creation_timestamp == msg.creation_timestamp()){
+                        Some(rotated) if rotated.psw_hash != hash_string(&psw) => {
+                            missing.push(msg.clone());
+                            continue;
+                        },
+                        Some(rotated) => Zeroizing::new(rotated.tangle_psw.clone()),
+                        None => psw.clone(),
+                    };
+                    to_import.push((&category.category, msg, psw, tangle_psw));
+                }
+            }
+        }
+
+        let imported = stream::iter(to_import.iter())
+            .map(|(category, msg, psw, tangle_psw)| {
+                let transport = self.transport.clone();
+                async move {
+                    let _permit = run.permits.acquire().await
+                        .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Daily, msg.address().to_string(), err.into()))?;
+                    msg.backup(category, psw, tangle_psw, backup_psw, transport).await
+                }
+            })
+            .buffered(run.options.max_concurrency())
+            .try_collect()
+            .await?;
+        Ok((imported, missing))
+    }
+
+    //
+    // Rebuild the tree from a backup without reading from the tangle.
+    // With `resync` the announcements published after the backup are read from the tangle,
+    // a failure of the resync is reported but doesn't fail the restore
+    //
+    pub async fn restore_backup(bytes: &[u8], psw: &str, resync: bool) -> errors::Result<Self>{
+        let opened = open_envelope(BACKUP_MAGIC, BACKUP_FORMAT_VERSION, bytes, psw)?;
+        let transport = Arc::new(TangleTransport::new(opened.network.clone()));
+        RootChannel::restore_opened_backup(opened, psw, transport, resync, ImportOptions::default()).await
+    }
+
+    pub async fn restore_backup_with_transport(bytes: &[u8], psw: &str, transport: Arc<dyn Transport>, resync: bool) -> errors::Result<Self>{
+        RootChannel::restore_backup_with_options(bytes, psw, transport, resync, ImportOptions::default()).await
+    }
+
+    //
+    // Restore with the cache policy of the options, that are used by the resync as well
+    //
+    pub async fn restore_backup_with_options(bytes: &[u8], psw: &str, transport: Arc<dyn Transport>, resync: bool,
+                                             options: ImportOptions) -> errors::Result<Self>{
+        let opened = open_envelope(BACKUP_MAGIC, BACKUP_FORMAT_VERSION, bytes, psw)?;
+        RootChannel::restore_opened_backup(opened, psw, transport, resync, options).await
+    }
+
+    #[instrument(name = "restore_backup", skip_all, fields(resync = resync))]
+    async fn restore_opened_backup(opened: Opened, psw: &str, transport: Arc<dyn Transport>, resync: bool, options: ImportOptions) -> errors::Result<Self>{
+        let backup: TreeBackup = bincode::deserialize(&opened.plain)?;
+
+        let root = transport.import_from_bytes(&backup.root.state, psw).await
+            .map_err(|err| ChannelManagerError::import_failed(ChannelLayer::Root, backup.root.address.to_string(), err))?;
+        let cache = Arc::new(DailyChannelCache::new(options.daily_channels_cache_policy()));
+        let mut categories = vec![];
+        for category in backup.categories.iter() {
+            let ch = CategoryChannel::restore(category, psw, backup.timezone, transport.clone(), cache.clone()).await?;
+            categories.push((Arc::new(Mutex::new(ch)), category.category.clone()));
+        }
+        for daily in backup.daily_channels.iter() {
+            let (key, manager) = DailyChannelManager::restore(daily, psw, backup.timezone, transport.clone()).await?;
+            cache.insert(key, manager).await;
+        }
+        info!(categories = categories.len(), daily_channels = backup.daily_channels.len(), created_at = backup.created_at, "backup restored");
+
+        let mut root = RootChannel{
+            root_info: writer_info(root.as_ref()),
+            root: Mutex::new(root),
+            categories,
+            opened: true,
+            timezone: backup.timezone,
+            psw: Zeroizing::new(psw.to_string()),
+            tangle_psw: Some(Zeroizing::new(backup.tangle_psw.clone())).filter(|tangle_psw| tangle_psw.as_str() != psw),
+            keystore: None,
+            transport,
+            daily_channels_cache: cache,
+        };
+        if resync{
+            if let Err(err) = root.resync_with_options(&options).await{
+                warn!(error = %err, "resync with the tangle failed, the tree is restored as it was in the backup");
+            }
+        }
+        Ok(root)
+    }
+
+    //
+    // Read from the tangle the announcements that are not known yet: new categories on the root,
+    // new actors on the categories and new daily channels of the loaded actors
+    //
+    pub async fn resync(&mut self) -> errors::Result<()>{
+        self.resync_with_options(&ImportOptions::default()).await
+    }
+
+    #[instrument(name = "resync", skip_all)]
+    async fn resync_with_options(&mut self, options: &ImportOptions) -> errors::Result<()>{
+        let info = self.channel_info();
+        let announced = RootChannel::read_categories_channels_info(info.channel_id(), info.announce_id(), self.transport.as_ref()).await?;
+        let missing = CategoryChannelsInfo{
+            categories: announced.categories.into_iter()
+                .filter(|c| !self.categories.iter().any(|(_, category)| category == &c.category))
+                .collect(),
+            timezone: self.timezone,
+        };
+        let psw = self.tangle_psw()?;
+        let new_categories = RootChannel::import_categories(missing, &psw, self.transport.clone(), self.daily_channels_cache.clone(), options).await?;
+        for ch in new_categories {
+            let category = ch.category().clone();
+            info!(category = %category.to_string(), "category added by resync");
+            self.categories.push((Arc::new(Mutex::new(ch)), category));
+        }
+
+        let mut added = 0;
+        for (category, _) in self.categories.iter() {
+            added += category.lock().await.resync().await?;
+        }
+        info!(added, "tree resynced");
+        Ok(())
+    }
+}
+
+// Cache of the imported daily channels
+impl RootChannel{
+    pub async fn set_cache_policy(&self, policy: CachePolicy){
+        self.daily_channels_cache.set_policy(policy).await
+    }
+
+    pub async fn cache_stats(&self) -> CacheStats{
+        self.daily_channels_cache.stats().await
+    }
+
+    //
+    // Drop the cached daily channel of an actor in the given date, whatever password it was imported with.
+    // A channel is kept while a handle to it is still held, returns how many channels have been dropped
+    //
+    pub async fn evict_daily_channel(&self, category: Category, actor_id: &str, date: NaiveDate) -> usize{
+        let actor_id = actor_id.to_lowercase();
+        let evicted = self.daily_channels_cache.evict(|key: &CacheKey| {
+            key.category == category.id() && key.actor_id == actor_id && key.date == date
+        }).await;
+        info!(category = %category.to_string(), actor_id = %actor_id, date = %date_to_string(&date), evicted, "daily channel evicted from cache");
+        evicted
+    }
+
+    pub async fn evict_actor(&self, category: Category, actor_id: &str) -> usize{
+        let actor_id = actor_id.to_lowercase();
+        self.daily_channels_cache.evict(|key: &CacheKey| key.category == category.id() && key.actor_id == actor_id).await
+    }
+
+    pub async fn clear_cache(&self) -> usize{
+        self.daily_channels_cache.clear().await
     }
 }
 
 
 // Read APIs
 impl RootChannel{
-    pub fn actors_of_category(&self, category: Category) -> Vec<ActorChannelMsg>{
-        let cat = &self.categories.iter().find(|cat| category == cat.1).unwrap().0;
-        cat.lock().unwrap().actors_info()
+    pub fn categories(&self) -> Vec<Category>{
+        self.categories.iter().map(|(_, category)| category.clone()).collect()
+    }
+
+    //
+    // Category of the tree with the given id, custom categories included
+    //
+    pub fn category(&self, id: &str) -> errors::Result<Category>{
+        Category::from_registered(id, &self.categories())
+            .ok_or_else(|| ChannelManagerError::CategoryNotFound(id.to_string()))
     }
 
-    pub fn channels_of_actor(&self, category: Category, actor_id: &str) -> Vec<DailyChannelMsg>{
-        let cat = &self.categories.iter().find(|cat| category == cat.1).unwrap().0;
-        cat.lock().unwrap().channels_of_actor(actor_id)
+    pub async fn actors_of_category(&self, category: Category) -> errors::Result<Vec<ActorChannelMsg>>{
+        Ok(self.category_channel(&category)?.lock().await.actors_info())
+    }
+
+    //
+    // The actor channel is imported from the tangle the first time its daily channels are requested
+    //
+    pub async fn channels_of_actor(&self, category: Category, actor_id: &str) -> errors::Result<Vec<DailyChannelMsg>>{
+        let cat = self.category_channel(&category)?;
+        let psw = self.tangle_psw()?;
+        let res = cat.lock().await.channels_of_actor(actor_id, &psw).await;
+        res
     }
 
 }
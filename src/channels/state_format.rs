@@ -0,0 +1,364 @@
+use crate::channels::{Category, NetworkConfig};
+use crate::channels::crypto::{self, KdfParams, NONCE_LEN};
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::utils::Tz;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use bincode::Options;
+use base64::{encode_config, URL_SAFE_NO_PAD, decode_config};
+use zeroize::{Zeroize, Zeroizing};
+
+//
+// Envelope of the exported states and backups:
+// | magic | format version (u16) | kdf params | network | nonce | ciphertext + tag |
+// Everything before the ciphertext is authenticated as associated data.
+// Daily channel states exported before the envelope (format 0) are migrated to the current one on import,
+// they were created on UTC days
+//
+const STATE_MAGIC: [u8; 4] = *b"B4TS";
+pub const STATE_FORMAT_VERSION: u16 = 1;
+const LEGACY_FORMAT_VERSION: u16 = 0;
+
+#[derive(Serialize, Deserialize)]
+struct EnvelopeHeader{
+    magic: [u8; 4],
+    version: u16,
+    kdf: KdfParams,
+    network: NetworkConfig,
+    nonce: [u8; NONCE_LEN],
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope{
+    header: EnvelopeHeader,
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct PayloadRef<'a>{
+    channel_state: &'a [u8],
+    category: &'a Category,
+    actor_id: &'a str,
+    creation_timestamp: i64,
+    timezone: &'a Tz,
+}
+
+#[derive(Deserialize)]
+struct Payload{
+    channel_state: Vec<u8>,
+    category: Category,
+    actor_id: String,
+    creation_timestamp: i64,
+    timezone: Tz,
+}
+
+//
+// Decrypted state of a daily channel, whatever format it has been read from
+//
+pub (crate) struct DailyChannelState{
+    pub (crate) channel_state: Vec<u8>,
+    pub (crate) category: Category,
+    pub (crate) actor_id: String,
+    pub (crate) creation_timestamp: i64,
+    pub (crate) timezone: Tz,
+    pub (crate) network: NetworkConfig,
+    pub (crate) format_version: u16,
+}
+
+impl DailyChannelState{
+    pub (crate) fn new(channel_state: Vec<u8>, category: Category, actor_id: &str, creation_timestamp: i64, timezone: Tz, network: NetworkConfig) -> Self {
+        DailyChannelState{ channel_state, category, actor_id: actor_id.to_string(), creation_timestamp, timezone, network, format_version: STATE_FORMAT_VERSION }
+    }
+
+    pub (crate) fn is_outdated(&self) -> bool {
+        self.format_version < STATE_FORMAT_VERSION
+    }
+
+    //
+    // The state is always written in the current format
+    //
+    pub (crate) fn encrypt(&self, state_psw: &str) -> errors::Result<String>{
+        let payload = PayloadRef{
+            channel_state: &self.channel_state,
+            category: &self.category,
+            actor_id: &self.actor_id,
+            creation_timestamp: self.creation_timestamp,
+            timezone: &self.timezone,
+        };
+        let plain = Zeroizing::new(bincode::serialize(&payload)?);
+        let bytes = seal_envelope(STATE_MAGIC, STATE_FORMAT_VERSION, &self.network, &plain, state_psw)?;
+        Ok(encode_config(&bytes, URL_SAFE_NO_PAD))
+    }
+
+    pub (crate) fn decrypt(base64: &str, state_psw: &str) -> errors::Result<Self>{
+        let bytes = decode_config(base64.as_bytes(), URL_SAFE_NO_PAD)?;
+        if bytes.starts_with(&STATE_MAGIC){
+            let opened = open_envelope(STATE_MAGIC, STATE_FORMAT_VERSION, &bytes, state_psw)?;
+            let payload: Payload = decode_exact(&opened.plain)?;
+            return Ok(DailyChannelState{
+                channel_state: payload.channel_state,
+                category: payload.category,
+                actor_id: payload.actor_id,
+                creation_timestamp: payload.creation_timestamp,
+                timezone: payload.timezone,
+                network: opened.network,
+                format_version: opened.version,
+            });
+        }
+
+        let plain = crypto::open_legacy(&bytes, state_psw)?;
+        decode_legacy(&plain)
+    }
+
+}
+
+pub (crate) struct Opened{
+    pub (crate) version: u16,
+    pub (crate) network: NetworkConfig,
+    pub (crate) plain: Zeroizing<Vec<u8>>,
+}
+
+pub (crate) fn seal_envelope(magic: [u8; 4], version: u16, network: &NetworkConfig, plain: &[u8], psw: &str) -> errors::Result<Vec<u8>>{
+    let header = EnvelopeHeader{
+        magic,
+        version,
+        kdf: KdfParams::generate(),
+        network: network.clone(),
+        nonce: crypto::random_nonce(),
+    };
+    let ciphertext = seal(&header, &header.kdf, &header.nonce, plain, psw)?;
+    Ok(bincode::serialize(&Envelope{ header, ciphertext })?)
+}
+
+pub (crate) fn open_envelope(magic: [u8; 4], max_version: u16, bytes: &[u8], psw: &str) -> errors::Result<Opened>{
+    check_magic(magic, bytes)?;
+    let envelope: Envelope = decode_exact(bytes)?;
+    let header = envelope.header;
+    check_version(header.version, max_version)?;
+    let plain = open(&header, &header.kdf, &header.nonce, &envelope.ciphertext, psw)?;
+    Ok(Opened{ version: header.version, network: header.network, plain })
+}
+
+//
+// Envelope of the files that don't belong to a network, e.g. the keystore:
+// | magic | format version (u16) | kdf params | nonce | ciphertext + tag |
+//
+#[derive(Serialize, Deserialize)]
+struct LocalEnvelopeHeader{
+    magic: [u8; 4],
+    version: u16,
+    kdf: KdfParams,
+    nonce: [u8; NONCE_LEN],
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocalEnvelope{
+    header: LocalEnvelopeHeader,
+    ciphertext: Vec<u8>,
+}
+
+pub (crate) fn seal_local_envelope(magic: [u8; 4], version: u16, plain: &[u8], psw: &str) -> errors::Result<Vec<u8>>{
+    let header = LocalEnvelopeHeader{ magic, version, kdf: KdfParams::generate(), nonce: crypto::random_nonce() };
+    let ciphertext = seal(&header, &header.kdf, &header.nonce, plain, psw)?;
+    Ok(bincode::serialize(&LocalEnvelope{ header, ciphertext })?)
+}
+
+pub (crate) fn open_local_envelope(magic: [u8; 4], max_version: u16, bytes: &[u8], psw: &str) -> errors::Result<(u16, Zeroizing<Vec<u8>>)>{
+    check_magic(magic, bytes)?;
+    let envelope: LocalEnvelope = decode_exact(bytes)?;
+    let header = envelope.header;
+    check_version(header.version, max_version)?;
+    let plain = open(&header, &header.kdf, &header.nonce, &envelope.ciphertext, psw)?;
+    Ok((header.version, plain))
+}
+
+fn check_magic(magic: [u8; 4], bytes: &[u8]) -> errors::Result<()>{
+    if !bytes.starts_with(&magic){
+        return Err(ChannelManagerError::StateCorrupted("Unknown magic bytes".to_string()));
+    }
+    Ok(())
+}
+
+fn check_version(version: u16, max_version: u16) -> errors::Result<()>{
+    if version > max_version{
+        return Err(ChannelManagerError::UnsupportedStateFormat(version));
+    }
+    Ok(())
+}
+
+// The whole header is authenticated as associated data
+fn seal<H: Serialize>(header: &H, kdf: &KdfParams, nonce: &[u8; NONCE_LEN], plain: &[u8], psw: &str) -> errors::Result<Vec<u8>>{
+    let aad = bincode::serialize(header)?;
+    let key = kdf.derive_key(psw)?;
+    crypto::encrypt(&key, nonce, plain, &aad)
+}
+
+fn open<H: Serialize>(header: &H, kdf: &KdfParams, nonce: &[u8; NONCE_LEN], ciphertext: &[u8], psw: &str) -> errors::Result<Zeroizing<Vec<u8>>>{
+    let aad = bincode::serialize(header)?;
+    let key = kdf.derive_key(psw)?;
+    crypto::decrypt(&key, nonce, ciphertext, &aad)
+}
+
+impl Drop for DailyChannelState{
+    fn drop(&mut self) {
+        self.channel_state.zeroize();
+    }
+}
+
+// Categories of the releases before the envelope
+#[derive(Deserialize)]
+enum FixedCategory{
+    Trucks,
+    Scales,
+    BioCells
+}
+
+impl From<FixedCategory> for Category{
+    fn from(category: FixedCategory) -> Self {
+        match category{
+            FixedCategory::Trucks => Category::trucks(),
+            FixedCategory::Scales => Category::scales(),
+            FixedCategory::BioCells => Category::biocells(),
+        }
+    }
+}
+
+// Layout of the states exported before the envelope, with the password and the mainnet flag in the payload
+#[derive(Deserialize)]
+struct MainnetFlagState{
+    channel_state: Vec<u8>,
+    category: FixedCategory,
+    actor_id: String,
+    creation_timestamp: i64,
+    state_psw: String,
+    mainnet: bool,
+}
+
+fn decode_legacy(plain: &[u8]) -> errors::Result<DailyChannelState>{
+    let mut state = decode_exact::<MainnetFlagState>(plain)?;
+    state.state_psw.zeroize();
+    let network = if state.mainnet { NetworkConfig::mainnet() } else { NetworkConfig::devnet() };
+    Ok(DailyChannelState{
+        channel_state: state.channel_state,
+        category: state.category.into(),
+        actor_id: state.actor_id,
+        creation_timestamp: state.creation_timestamp,
+        timezone: Tz::UTC,
+        network,
+        format_version: LEGACY_FORMAT_VERSION,
+    })
+}
+
+fn decode_exact<T: DeserializeOwned>(bytes: &[u8]) -> errors::Result<T>{
+    Ok(bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(bytes)?)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::channels::crypto::seal_legacy;
+    use serde::Serialize;
+
+    const PSW: &str = "state_psw";
+
+    fn current_state() -> DailyChannelState{
+        DailyChannelState::new(vec![1, 2, 3], Category::trucks(), "truck1", 1_632_434_400, Tz::Europe__Rome, NetworkConfig::devnet())
+    }
+
+    // Layout written by the releases before the envelope, kept as it was serialized
+    #[derive(Serialize)]
+    enum LegacyCategory{
+        Trucks,
+    }
+
+    #[derive(Serialize)]
+    struct LegacyMainnetFlagState{
+        channel_state: Vec<u8>,
+        category: LegacyCategory,
+        actor_id: String,
+        creation_timestamp: i64,
+        state_psw: String,
+        mainnet: bool,
+    }
+
+    fn legacy_plain() -> Vec<u8>{
+        let legacy = LegacyMainnetFlagState{
+            channel_state: vec![1, 2, 3], category: LegacyCategory::Trucks, actor_id: "truck1".to_string(),
+            creation_timestamp: 1_632_441_600, state_psw: PSW.to_string(), mainnet: true
+        };
+        bincode::serialize(&legacy).unwrap()
+    }
+
+    fn legacy_state() -> String{
+        encode_config(seal_legacy(&legacy_plain(), PSW), URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn current_format_round_trip(){
+        let exported = current_state().encrypt(PSW).unwrap();
+        let state = DailyChannelState::decrypt(&exported, PSW).unwrap();
+        assert_eq!(state.channel_state, vec![1, 2, 3]);
+        assert_eq!(state.category, Category::trucks());
+        assert_eq!(state.actor_id, "truck1");
+        assert_eq!(state.creation_timestamp, 1_632_434_400);
+        assert_eq!(state.timezone, Tz::Europe__Rome);
+        assert_eq!(state.network, NetworkConfig::devnet());
+        assert_eq!(state.format_version, STATE_FORMAT_VERSION);
+        assert!(!state.is_outdated());
+    }
+
+    #[test]
+    fn legacy_state_is_read(){
+        let state = DailyChannelState::decrypt(&legacy_state(), PSW).unwrap();
+        assert_eq!(state.channel_state, vec![1, 2, 3]);
+        assert_eq!(state.category, Category::trucks());
+        assert_eq!(state.actor_id, "truck1");
+        assert_eq!(state.creation_timestamp, 1_632_441_600);
+        assert_eq!(state.timezone, Tz::UTC);
+        assert_eq!(state.network, NetworkConfig::mainnet());
+        assert_eq!(state.format_version, LEGACY_FORMAT_VERSION);
+        assert!(state.is_outdated());
+    }
+
+    #[test]
+    fn legacy_state_is_upgraded(){
+        let upgraded = DailyChannelState::decrypt(&legacy_state(), PSW).unwrap().encrypt(PSW).unwrap();
+        let state = DailyChannelState::decrypt(&upgraded, PSW).unwrap();
+        assert_eq!(state.format_version, STATE_FORMAT_VERSION);
+        assert_eq!(state.timezone, Tz::UTC);
+        assert_eq!(state.creation_timestamp, 1_632_441_600);
+    }
+
+    #[test]
+    fn wrong_password(){
+        let exported = current_state().encrypt(PSW).unwrap();
+        assert!(matches!(DailyChannelState::decrypt(&exported, "wrong"), Err(ChannelManagerError::WrongPassword)));
+        assert!(matches!(DailyChannelState::decrypt(&legacy_state(), "wrong"), Err(ChannelManagerError::WrongPassword)));
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected(){
+        let mut bytes = decode_config(current_state().encrypt(PSW).unwrap(), URL_SAFE_NO_PAD).unwrap();
+        bytes.push(0);
+        let exported = encode_config(&bytes, URL_SAFE_NO_PAD);
+        assert!(matches!(DailyChannelState::decrypt(&exported, PSW), Err(ChannelManagerError::StateCorrupted(_))));
+
+        let mut plain = legacy_plain();
+        plain.push(0);
+        let legacy = encode_config(seal_legacy(&plain, PSW), URL_SAFE_NO_PAD);
+        assert!(matches!(DailyChannelState::decrypt(&legacy, PSW), Err(ChannelManagerError::StateCorrupted(_))));
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected(){
+        let bytes = seal_envelope(STATE_MAGIC, STATE_FORMAT_VERSION + 1, &NetworkConfig::devnet(), &[], PSW).unwrap();
+        let exported = encode_config(&bytes, URL_SAFE_NO_PAD);
+        assert!(matches!(
+            DailyChannelState::decrypt(&exported, PSW),
+            Err(ChannelManagerError::UnsupportedStateFormat(version)) if version == STATE_FORMAT_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::channels::{ChannelInfo, NetworkConfig, ChannelMessage};
+use crate::channels::message_reader::parse_message;
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::transport::{StreamsReader, Transport, TangleTransport};
+use futures::stream::{self, Stream};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+//
+// Position of a consumer on a channel, it can be saved and given back to a new subscription
+// so that the messages already delivered are skipped
+//
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageCursor{
+    channel_id: String,
+    position: u64,
+    last_msg_id: Option<String>,
+}
+
+impl MessageCursor{
+    pub fn new(channel_info: &ChannelInfo) -> Self {
+        MessageCursor { channel_id: channel_info.channel_id().to_string(), position: 0, last_msg_id: None }
+    }
+
+    pub fn channel_id(&self) -> &str {
+        &self.channel_id
+    }
+
+    //
+    // Number of messages delivered so far
+    //
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn last_msg_id(&self) -> Option<&str> {
+        self.last_msg_id.as_deref()
+    }
+}
+
+//
+// The channel is polled every poll interval. When the node can't be reached the subscription waits
+// the poll interval, doubled at every failure up to the max backoff, and attaches again
+//
+#[derive(Debug, Clone)]
+pub struct SubscriptionOptions{
+    poll_interval: Duration,
+    max_backoff: Duration,
+    cursor: Option<MessageCursor>,
+    key_nonce: Option<([u8;32], [u8;24])>,
+}
+
+impl SubscriptionOptions{
+    pub fn new() -> Self {
+        SubscriptionOptions { poll_interval: DEFAULT_POLL_INTERVAL, max_backoff: DEFAULT_MAX_BACKOFF, cursor: None, key_nonce: None }
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn resume_from(mut self, cursor: MessageCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    pub fn key_nonce(mut self, key_nonce: Option<([u8;32], [u8;24])>) -> Self {
+        self.key_nonce = key_nonce;
+        self
+    }
+}
+
+impl Default for SubscriptionOptions{
+    fn default() -> Self {
+        SubscriptionOptions::new()
+    }
+}
+
+//
+// Message delivered by a subscription with the cursor that follows it
+//
+#[derive(Debug, Clone)]
+pub struct DeliveredMessage{
+    pub message: ChannelMessage,
+    pub cursor: MessageCursor,
+}
+
+//
+// Endless stream of the new messages of a channel.
+// A message that can't be decoded is delivered as an error and the cursor moves past it
+//
+pub struct Subscription{
+    inner: Pin<Box<dyn Stream<Item = errors::Result<DeliveredMessage>> + Send>>,
+}
+
+impl Subscription{
+    pub fn new(channel_info: &ChannelInfo, network: NetworkConfig, options: SubscriptionOptions) -> errors::Result<Self>{
+        Subscription::new_with_transport(channel_info, Arc::new(TangleTransport::new(network)), options)
+    }
+
+    pub fn new_with_transport(channel_info: &ChannelInfo, transport: Arc<dyn Transport>, options: SubscriptionOptions) -> errors::Result<Self>{
+        let cursor = match &options.cursor{
+            None => MessageCursor::new(channel_info),
+            Some(cursor) if cursor.channel_id == channel_info.channel_id() => cursor.clone(),
+            Some(cursor) => return Err(ChannelManagerError::InvalidCursor(cursor.channel_id.clone()))
+        };
+        let state = PollState{
+            transport,
+            info: channel_info.clone(),
+            reader: None,
+            pending: VecDeque::new(),
+            fetched: 0,
+            backoff: options.poll_interval,
+            cursor,
+            options,
+        };
+        Ok(Subscription{ inner: Box::pin(stream::unfold(state, PollState::next)) })
+    }
+}
+
+impl Stream for Subscription{
+    type Item = errors::Result<DeliveredMessage>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+struct PollState{
+    transport: Arc<dyn Transport>,
+    info: ChannelInfo,
+    reader: Option<Box<dyn StreamsReader>>,
+    pending: VecDeque<(String, Vec<u8>, Vec<u8>)>,
+    fetched: u64,
+    backoff: Duration,
+    cursor: MessageCursor,
+    options: SubscriptionOptions,
+}
+
+impl PollState{
+    async fn next(mut self) -> Option<(errors::Result<DeliveredMessage>, Self)>{
+        loop {
+            if let Some((msg_id, p, m)) = self.pending.pop_front(){
+                self.cursor.position += 1;
+                self.cursor.last_msg_id = Some(msg_id.clone());
+                let res = parse_message(self.info.channel_id(), &self.options.key_nonce, msg_id, &p, &m)
+                    .map(|message| DeliveredMessage{ message, cursor: self.cursor.clone() });
+                return Some((res, self));
+            }
+
+            match self.reader.as_mut(){
+                None => self.attach().await,
+                Some(reader) => {
+                    tokio::time::sleep(self.options.poll_interval).await;
+                    match reader.fetch_raw_msgs().await{
+                        Ok(msgs) => self.enqueue(msgs),
+                        Err(err) => {
+                            self.reader = None;
+                            self.back_off(err).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    //
+    // Messages fetched again after a new attach and already delivered are skipped,
+    // the pending queue is always empty when a fetch happens
+    //
+    fn enqueue(&mut self, msgs: Vec<(String, Vec<u8>, Vec<u8>)>){
+        for msg in msgs {
+            self.fetched += 1;
+            if self.fetched > self.cursor.position{
+                self.pending.push_back(msg);
+            }
+        }
+    }
+
+    async fn attach(&mut self){
+        let mut reader = self.transport.create_reader(self.info.channel_id(), self.info.announce_id());
+        let res = match reader.attach().await{
+            Ok(_) => reader.fetch_raw_msgs().await,
+            Err(err) => Err(err)
+        };
+        match res{
+            Ok(msgs) => {
+                debug!(address = %self.info.to_string(), position = self.cursor.position, "subscription attached");
+                self.backoff = self.options.poll_interval;
+                self.fetched = 0;
+                self.reader = Some(reader);
+                self.enqueue(msgs);
+            },
+            Err(err) => self.back_off(err).await
+        }
+    }
+
+    async fn back_off(&mut self, err: anyhow::Error){
+        warn!(address = %self.info.to_string(), error = %err, retry_in_ms = self.backoff.as_millis() as u64, "subscription can't reach the channel");
+        tokio::time::sleep(self.backoff).await;
+        self.backoff = (self.backoff * 2).min(self.options.max_backoff);
+    }
+}
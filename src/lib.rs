@@ -1,3 +1,5 @@
 pub mod channels;
 pub mod utils;
+pub mod transport;
+pub mod messages;
 
@@ -0,0 +1,172 @@
+use crate::channels::Category;
+use crate::channels::errors;
+use crate::messages::{CategoryMessage, ChannelReference, check, check_version, required, first_version};
+use crate::utils::current_time_secs;
+use serde::{Serialize, Deserialize};
+
+pub const BIOCELL_SCHEMA_VERSION: u16 = 1;
+
+//
+// Digestion cycle of a biocell, started with the load of a weighing (kg) and monitored by periodic measurements.
+// Temperature is in °C, humidity is a percentage
+//
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BiocellMessage{
+    #[serde(default = "first_version")]
+    schema_version: u16,
+    plant: String,
+    digestor_id: String,
+    max_capacity: u32,
+    start_timestamp: i64,
+    last_measurement: MeasurementsMessage,
+    scale_info: ScaleInfo
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MeasurementsMessage{
+    timestamp: i64,
+    temperature: f32,
+    humidity: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScaleInfo{
+    weight: f32,
+    msg_info: ChannelReference,
+}
+
+impl ScaleInfo{
+    pub fn new(weight: f32, msg_info: ChannelReference) -> Self {
+        ScaleInfo { weight, msg_info }
+    }
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn msg_info(&self) -> &ChannelReference {
+        &self.msg_info
+    }
+}
+
+impl MeasurementsMessage{
+    pub fn new(timestamp: i64, temperature: f32, humidity: f32) -> Self {
+        MeasurementsMessage { timestamp, temperature, humidity }
+    }
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+    pub fn humidity(&self) -> f32 {
+        self.humidity
+    }
+}
+
+impl BiocellMessage{
+    pub fn builder() -> BiocellMessageBuilder {
+        BiocellMessageBuilder::default()
+    }
+    pub fn plant(&self) -> &str {
+        &self.plant
+    }
+    pub fn digestor_id(&self) -> &str {
+        &self.digestor_id
+    }
+    pub fn max_capacity(&self) -> u32 {
+        self.max_capacity
+    }
+    pub fn start_timestamp(&self) -> i64 {
+        self.start_timestamp
+    }
+    pub fn last_measurement(&self) -> &MeasurementsMessage {
+        &self.last_measurement
+    }
+    pub fn scale_info(&self) -> &ScaleInfo {
+        &self.scale_info
+    }
+}
+
+impl CategoryMessage for BiocellMessage{
+    const VERSION: u16 = BIOCELL_SCHEMA_VERSION;
+
+    fn category() -> Category {
+        Category::biocells()
+    }
+
+    fn schema_version(&self) -> u16 {
+        self.schema_version
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.last_measurement.timestamp
+    }
+
+    fn validate(&self) -> errors::Result<()> {
+        check_version(self)?;
+        check(!self.plant.trim().is_empty(), "plant is empty")?;
+        check(!self.digestor_id.trim().is_empty(), "digestor id is empty")?;
+        check(self.max_capacity > 0, "max capacity must be positive")?;
+        check(self.scale_info.weight >= 0.0, "weight can't be negative")?;
+        check(self.last_measurement.timestamp >= self.start_timestamp, "measurement taken before the start")?;
+        check((0.0..=100.0).contains(&self.last_measurement.humidity), "humidity must be between 0 and 100")
+    }
+}
+
+#[derive(Default)]
+pub struct BiocellMessageBuilder{
+    plant: Option<String>,
+    digestor_id: Option<String>,
+    max_capacity: Option<u32>,
+    start_timestamp: Option<i64>,
+    last_measurement: Option<MeasurementsMessage>,
+    scale_info: Option<ScaleInfo>,
+}
+
+impl BiocellMessageBuilder{
+    pub fn plant(mut self, plant: &str) -> Self {
+        self.plant = Some(plant.to_string());
+        self
+    }
+
+    pub fn digestor_id(mut self, digestor_id: &str) -> Self {
+        self.digestor_id = Some(digestor_id.to_string());
+        self
+    }
+
+    pub fn max_capacity(mut self, max_capacity: u32) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    //
+    // Defaults to now
+    //
+    pub fn start_timestamp(mut self, start_timestamp: i64) -> Self {
+        self.start_timestamp = Some(start_timestamp);
+        self
+    }
+
+    pub fn last_measurement(mut self, last_measurement: MeasurementsMessage) -> Self {
+        self.last_measurement = Some(last_measurement);
+        self
+    }
+
+    pub fn scale_info(mut self, scale_info: ScaleInfo) -> Self {
+        self.scale_info = Some(scale_info);
+        self
+    }
+
+    pub fn build(self) -> errors::Result<BiocellMessage>{
+        let msg = BiocellMessage{
+            schema_version: BIOCELL_SCHEMA_VERSION,
+            plant: required(self.plant, "plant")?,
+            digestor_id: required(self.digestor_id, "digestor id")?,
+            max_capacity: required(self.max_capacity, "max capacity")?,
+            start_timestamp: self.start_timestamp.unwrap_or_else(current_time_secs),
+            last_measurement: required(self.last_measurement, "last measurement")?,
+            scale_info: required(self.scale_info, "scale info")?,
+        };
+        msg.validate()?;
+        Ok(msg)
+    }
+}
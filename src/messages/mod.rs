@@ -0,0 +1,90 @@
+pub mod trucks;
+pub mod scales;
+pub mod biocells;
+mod provenance;
+mod verification;
+pub use trucks::{TruckMessage, TruckMessageBuilder, TruckStepMessage, TRUCK_SCHEMA_VERSION};
+pub use scales::{ScaleMessage, ScaleMessageBuilder, TruckInfo, SCALE_SCHEMA_VERSION};
+pub use biocells::{BiocellMessage, BiocellMessageBuilder, MeasurementsMessage, ScaleInfo, BIOCELL_SCHEMA_VERSION};
+pub use provenance::{ReferenceResolver, ProvenanceChain, ProvenanceHop};
+pub use verification::{VerificationReport, HopReport, HopStatus, LinkIssue, IssueKind};
+
+use crate::channels::{Category, ChannelInfo};
+use crate::channels::errors::{self, ChannelManagerError};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+//
+// Schema of the messages published on the daily channels of a category.
+// Every message carries the version of the schema it was written with, messages written before
+// the version was introduced are read as version 1
+//
+pub trait CategoryMessage: Serialize + DeserializeOwned{
+    const VERSION: u16;
+
+    fn category() -> Category;
+    fn schema_version(&self) -> u16;
+    //
+    // Time the message refers to, it has to fall on the day of the daily channel it is published on
+    //
+    fn timestamp(&self) -> i64;
+    fn validate(&self) -> errors::Result<()>;
+}
+
+//
+// Reference to a message published on another channel, e.g. the truck message a weighing refers to
+//
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelReference{
+    channel_address: String,
+    msg_id: String,
+}
+
+impl ChannelReference{
+    pub fn new(channel_id: String, announce_id: String, msg_id: String) -> Self {
+        ChannelReference {
+            channel_address: format!("{}:{}", channel_id, announce_id),
+            msg_id
+        }
+    }
+
+    //
+    // Address of the referenced channel in the form channel_id:announce_id
+    //
+    pub fn channel_address(&self) -> &str {
+        &self.channel_address
+    }
+
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    pub fn channel_info(&self) -> errors::Result<ChannelInfo>{
+        match self.channel_address.split_once(':'){
+            Some((channel_id, announce_id)) if !channel_id.is_empty() && !announce_id.is_empty() =>
+                Ok(ChannelInfo::new(channel_id.to_string(), announce_id.to_string())),
+            _ => Err(ChannelManagerError::InvalidMessage(format!("Malformed channel address {}", self.channel_address)))
+        }
+    }
+}
+
+pub (crate) fn first_version() -> u16 {
+    1
+}
+
+pub (crate) fn check(condition: bool, msg: &str) -> errors::Result<()>{
+    if condition { Ok(()) } else { Err(ChannelManagerError::InvalidMessage(msg.to_string())) }
+}
+
+pub (crate) fn check_version<M: CategoryMessage>(msg: &M) -> errors::Result<()>{
+    if msg.schema_version() > M::VERSION{
+        return Err(ChannelManagerError::InvalidMessage(format!(
+            "{} schema version {} is not supported by this version", M::category().to_string(), msg.schema_version()
+        )));
+    }
+    Ok(())
+}
+
+pub (crate) fn required<T>(field: Option<T>, name: &str) -> errors::Result<T>{
+    field.ok_or_else(|| ChannelManagerError::InvalidMessage(format!("{} is missing", name)))
+}
@@ -0,0 +1,102 @@
+use crate::channels::{ChannelMessage, MessageReader, NetworkConfig};
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::messages::{BiocellMessage, ScaleMessage, TruckMessage, ChannelReference};
+use crate::transport::{Transport, TangleTransport};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+//
+// Message reached following a reference, decoded with the schema expected at that point of the chain
+//
+#[derive(Debug, Clone)]
+pub struct ProvenanceHop<T>{
+    pub reference: ChannelReference,
+    pub message: ChannelMessage,
+    pub decoded: T,
+}
+
+//
+// Supply chain of a biocell cycle: the weighing that loaded the biocell and the truck delivery that was weighed
+//
+#[derive(Debug, Clone)]
+pub struct ProvenanceChain{
+    pub biocell: BiocellMessage,
+    pub scale: ProvenanceHop<ScaleMessage>,
+    pub truck: ProvenanceHop<TruckMessage>,
+}
+
+//
+// Follows the references between the messages of different channels.
+// Every channel is read once and its messages are kept for the next references to it
+//
+pub struct ReferenceResolver{
+    transport: Arc<dyn Transport>,
+    key_nonce: Option<([u8;32], [u8;24])>,
+    channels: HashMap<String, MessageReader>,
+}
+
+impl ReferenceResolver{
+    pub fn new(network: NetworkConfig) -> Self {
+        ReferenceResolver::new_with_transport(Arc::new(TangleTransport::new(network)))
+    }
+
+    pub fn new_with_transport(transport: Arc<dyn Transport>) -> Self {
+        ReferenceResolver { transport, key_nonce: None, channels: HashMap::new() }
+    }
+
+    //
+    // Key and nonce used to read the masked payloads of the referenced messages
+    //
+    pub fn key_nonce(mut self, key_nonce: Option<([u8;32], [u8;24])>) -> Self {
+        self.key_nonce = key_nonce;
+        self
+    }
+
+    //
+    // The channel is read again when the message is not among the ones already read, it may have been published later
+    //
+    pub async fn resolve(&mut self, reference: &ChannelReference) -> errors::Result<ChannelMessage>{
+        let address = reference.channel_address().to_string();
+        if !self.channels.contains_key(&address){
+            let info = reference.channel_info()?;
+            let reader = MessageReader::new_with_key_nonce(&info, self.transport.clone(), self.key_nonce).await?;
+            self.channels.insert(address.clone(), reader);
+        } else if let Some(reader) = self.channels.get_mut(&address){
+            if find(reader, reference).is_none(){
+                reader.read_messages().await?;
+            }
+        }
+
+        self.channels.get(&address)
+            .and_then(|reader| find(reader, reference))
+            .cloned()
+            .ok_or_else(|| ChannelManagerError::MessageNotFound(format!("{}/{}", address, reference.msg_id())))
+    }
+
+    pub async fn resolve_as<T: DeserializeOwned>(&mut self, reference: &ChannelReference) -> errors::Result<ProvenanceHop<T>>{
+        let message = self.resolve(reference).await?;
+        let decoded = message.public_as()?;
+        Ok(ProvenanceHop{ reference: reference.clone(), message, decoded })
+    }
+
+    //
+    // Walk a biocell cycle back to the weighing that loaded it and to the truck delivery
+    //
+    pub async fn trace_provenance(&mut self, biocell: &BiocellMessage) -> errors::Result<ProvenanceChain>{
+        let scale = self.resolve_as::<ScaleMessage>(biocell.scale_info().msg_info()).await?;
+        let truck = self.resolve_as::<TruckMessage>(scale.decoded.truck_info().msg_info()).await?;
+        debug!(digestor_id = %biocell.digestor_id(), scale = %scale.message.link(), truck = %truck.message.link(), "provenance traced");
+        Ok(ProvenanceChain{ biocell: biocell.clone(), scale, truck })
+    }
+
+    pub async fn trace_provenance_from(&mut self, biocell: &ChannelReference) -> errors::Result<ProvenanceChain>{
+        let biocell = self.resolve_as::<BiocellMessage>(biocell).await?;
+        self.trace_provenance(&biocell.decoded).await
+    }
+}
+
+fn find<'a>(reader: &'a MessageReader, reference: &ChannelReference) -> Option<&'a ChannelMessage>{
+    reader.msgs().iter().find(|msg| msg.msg_id() == reference.msg_id())
+}
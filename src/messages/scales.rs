@@ -0,0 +1,122 @@
+use crate::channels::Category;
+use crate::channels::errors;
+use crate::messages::{CategoryMessage, ChannelReference, check, check_version, required, first_version};
+use crate::utils::current_time_secs;
+use serde::{Serialize, Deserialize};
+
+pub const SCALE_SCHEMA_VERSION: u16 = 1;
+
+//
+// Weighing of a truck at the entrance of a plant.
+// The truck info refers to the truck message the weighing belongs to, the weight is in kg
+//
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScaleMessage{
+    #[serde(default = "first_version")]
+    schema_version: u16,
+    plant: String,
+    timestamp: i64,
+    truck_info: TruckInfo
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TruckInfo{
+    weight: f32,
+    driver: String,
+    license_plate: String,
+    msg_info: ChannelReference
+}
+
+impl TruckInfo{
+    pub fn new(weight: f32, driver: String, license_plate: String, msg_info: ChannelReference) -> Self {
+        TruckInfo { weight, driver, license_plate, msg_info }
+    }
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+    pub fn license_plate(&self) -> &str {
+        &self.license_plate
+    }
+    pub fn msg_info(&self) -> &ChannelReference {
+        &self.msg_info
+    }
+}
+
+impl ScaleMessage{
+    pub fn builder() -> ScaleMessageBuilder {
+        ScaleMessageBuilder::default()
+    }
+    pub fn plant(&self) -> &str {
+        &self.plant
+    }
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    pub fn truck_info(&self) -> &TruckInfo {
+        &self.truck_info
+    }
+}
+
+impl CategoryMessage for ScaleMessage{
+    const VERSION: u16 = SCALE_SCHEMA_VERSION;
+
+    fn category() -> Category {
+        Category::scales()
+    }
+
+    fn schema_version(&self) -> u16 {
+        self.schema_version
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn validate(&self) -> errors::Result<()> {
+        check_version(self)?;
+        check(!self.plant.trim().is_empty(), "plant is empty")?;
+        check(self.truck_info.weight > 0.0, "weight must be positive")?;
+        check(!self.truck_info.license_plate.trim().is_empty(), "license plate is empty")
+    }
+}
+
+#[derive(Default)]
+pub struct ScaleMessageBuilder{
+    plant: Option<String>,
+    timestamp: Option<i64>,
+    truck_info: Option<TruckInfo>,
+}
+
+impl ScaleMessageBuilder{
+    pub fn plant(mut self, plant: &str) -> Self {
+        self.plant = Some(plant.to_string());
+        self
+    }
+
+    //
+    // Defaults to now
+    //
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn truck_info(mut self, truck_info: TruckInfo) -> Self {
+        self.truck_info = Some(truck_info);
+        self
+    }
+
+    pub fn build(self) -> errors::Result<ScaleMessage>{
+        let msg = ScaleMessage{
+            schema_version: SCALE_SCHEMA_VERSION,
+            plant: required(self.plant, "plant")?,
+            timestamp: self.timestamp.unwrap_or_else(current_time_secs),
+            truck_info: required(self.truck_info, "truck info")?,
+        };
+        msg.validate()?;
+        Ok(msg)
+    }
+}
@@ -0,0 +1,153 @@
+use crate::channels::Category;
+use crate::channels::errors;
+use crate::messages::{CategoryMessage, check, check_version, required, first_version};
+use crate::utils::current_time_secs;
+use serde::{Serialize, Deserialize};
+
+pub const TRUCK_SCHEMA_VERSION: u16 = 1;
+
+//
+// Trip of a truck: plate, driver, departure timestamp and capacity in kg,
+// with the last step of the trip. Weights are in kg, fuel is the percentage left in the tank
+//
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TruckMessage{
+    #[serde(default = "first_version")]
+    schema_version: u16,
+    license_plate: String,
+    driver: String,
+    timestamp: i64,
+    max_weight: f32,
+    last_update: TruckStepMessage
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TruckStepMessage{
+    weight: f32,
+    total_weight: f32,
+    latitude: u32,
+    longitude: u32,
+    fuel: f32,
+}
+
+impl TruckStepMessage{
+    pub fn new(weight: f32, total_weight: f32, latitude: u32, longitude: u32, fuel: f32) -> Self {
+        TruckStepMessage { weight, total_weight, latitude, longitude, fuel }
+    }
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn total_weight(&self) -> f32 {
+        self.total_weight
+    }
+    pub fn latitude(&self) -> u32 {
+        self.latitude
+    }
+    pub fn longitude(&self) -> u32 {
+        self.longitude
+    }
+    pub fn fuel(&self) -> f32 {
+        self.fuel
+    }
+}
+
+impl TruckMessage{
+    pub fn builder() -> TruckMessageBuilder {
+        TruckMessageBuilder::default()
+    }
+    pub fn license_plate(&self) -> &str {
+        &self.license_plate
+    }
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+    pub fn max_weight(&self) -> f32 {
+        self.max_weight
+    }
+    pub fn last_update(&self) -> &TruckStepMessage {
+        &self.last_update
+    }
+}
+
+impl CategoryMessage for TruckMessage{
+    const VERSION: u16 = TRUCK_SCHEMA_VERSION;
+
+    fn category() -> Category {
+        Category::trucks()
+    }
+
+    fn schema_version(&self) -> u16 {
+        self.schema_version
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn validate(&self) -> errors::Result<()> {
+        check_version(self)?;
+        check(!self.license_plate.trim().is_empty(), "license plate is empty")?;
+        check(!self.driver.trim().is_empty(), "driver is empty")?;
+        check(self.max_weight > 0.0, "max weight must be positive")?;
+        let step = &self.last_update;
+        check(step.weight >= 0.0, "weight can't be negative")?;
+        check(step.weight <= step.total_weight, "weight exceeds the total weight")?;
+        check(step.total_weight <= self.max_weight, "total weight exceeds the max weight")?;
+        check((0.0..=100.0).contains(&step.fuel), "fuel must be between 0 and 100")
+    }
+}
+
+#[derive(Default)]
+pub struct TruckMessageBuilder{
+    license_plate: Option<String>,
+    driver: Option<String>,
+    timestamp: Option<i64>,
+    max_weight: Option<f32>,
+    last_update: Option<TruckStepMessage>,
+}
+
+impl TruckMessageBuilder{
+    pub fn license_plate(mut self, license_plate: &str) -> Self {
+        self.license_plate = Some(license_plate.to_string());
+        self
+    }
+
+    pub fn driver(mut self, driver: &str) -> Self {
+        self.driver = Some(driver.to_string());
+        self
+    }
+
+    //
+    // Defaults to now
+    //
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn max_weight(mut self, max_weight: f32) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    pub fn last_update(mut self, last_update: TruckStepMessage) -> Self {
+        self.last_update = Some(last_update);
+        self
+    }
+
+    pub fn build(self) -> errors::Result<TruckMessage>{
+        let msg = TruckMessage{
+            schema_version: TRUCK_SCHEMA_VERSION,
+            license_plate: required(self.license_plate, "license plate")?,
+            driver: required(self.driver, "driver")?,
+            timestamp: self.timestamp.unwrap_or_else(current_time_secs),
+            max_weight: required(self.max_weight, "max weight")?,
+            last_update: required(self.last_update, "last update")?,
+        };
+        msg.validate()?;
+        Ok(msg)
+    }
+}
@@ -0,0 +1,265 @@
+use crate::channels::{Category, DailyChannelInfo};
+use crate::channels::root_channel::RootChannel;
+use crate::channels::errors::{self, ChannelManagerError};
+use crate::messages::{BiocellMessage, ScaleMessage, TruckMessage, CategoryMessage, ChannelReference, ReferenceResolver};
+use crate::utils::{current_time_secs, timestamp_to_day, date_to_string, Tz};
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+//
+// Outcome of a hop: suspicious links are readable but don't match what the tree declares,
+// broken links can't be followed and end the chain
+//
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HopStatus{
+    Ok,
+    Suspicious,
+    Broken,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind{
+    ChannelUnreachable,
+    MessageNotFound,
+    UndecodableMessage,
+    ChannelNotInTree,
+    WrongCategory,
+    DateMismatch,
+    InvalidTimestamp,
+    InvalidContent,
+    LinkMismatch,
+}
+
+impl IssueKind{
+    pub fn severity(&self) -> HopStatus {
+        match self{
+            IssueKind::DateMismatch | IssueKind::InvalidContent | IssueKind::LinkMismatch => HopStatus::Suspicious,
+            _ => HopStatus::Broken
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LinkIssue{
+    pub kind: IssueKind,
+    pub detail: String,
+}
+
+//
+// Check of a message of the chain against the daily channel it was found on.
+// Category, actor and date are the ones registered in the tree for that channel
+//
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HopReport{
+    pub role: String,
+    pub expected_category: String,
+    pub reference: ChannelReference,
+    pub status: HopStatus,
+    pub category: Option<String>,
+    pub actor_id: Option<String>,
+    pub channel_date: Option<String>,
+    pub message_timestamp: Option<i64>,
+    pub message_date: Option<String>,
+    pub issues: Vec<LinkIssue>,
+}
+
+impl HopReport{
+    fn new(role: &str, expected_category: String, reference: &ChannelReference) -> Self {
+        HopReport{
+            role: role.to_string(), expected_category, reference: reference.clone(), status: HopStatus::Ok,
+            category: None, actor_id: None, channel_date: None, message_timestamp: None, message_date: None, issues: vec![]
+        }
+    }
+
+    fn add_issue(&mut self, kind: IssueKind, detail: String){
+        self.status = self.status.max(kind.severity());
+        self.issues.push(LinkIssue{ kind, detail });
+    }
+}
+
+//
+// Report of a provenance chain from the biocell back to the truck delivery, hops are in the order they were followed
+//
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VerificationReport{
+    pub root: String,
+    pub verified_at: i64,
+    pub status: HopStatus,
+    pub hops: Vec<HopReport>,
+}
+
+impl VerificationReport{
+    pub fn is_valid(&self) -> bool {
+        self.status == HopStatus::Ok
+    }
+
+    pub fn to_json(&self) -> errors::Result<String>{
+        serde_json::to_string_pretty(self).map_err(|e| ChannelManagerError::Encoding(e.to_string()))
+    }
+}
+
+impl ReferenceResolver{
+    //
+    // Checks every link of the chain starting from a biocell message: the referenced messages must exist,
+    // be published on a daily channel of the expected category in the tree and on the day of that channel.
+    // Every problem ends up in the report, channels that can't be read are reported as unreachable
+    //
+    pub async fn verify_provenance(&mut self, root: &RootChannel, biocell: &ChannelReference) -> VerificationReport{
+        let mut tree = TreeLookup::new(root);
+        let timezone = root.timezone();
+        let mut hops = vec![];
+
+        let (report, biocell_msg) = check_hop::<BiocellMessage>(self, &mut tree, timezone, "biocell", biocell).await;
+        hops.push(report);
+        if let Some(biocell_msg) = biocell_msg{
+            let (mut report, scale_msg) = check_hop::<ScaleMessage>(self, &mut tree, timezone, "scale", biocell_msg.scale_info().msg_info()).await;
+            if let Some(scale_msg) = &scale_msg{
+                if scale_msg.plant() != biocell_msg.plant(){
+                    report.add_issue(IssueKind::LinkMismatch, format!("weighing at plant {} loaded a biocell of plant {}", scale_msg.plant(), biocell_msg.plant()));
+                }
+            }
+            hops.push(report);
+
+            if let Some(scale_msg) = scale_msg{
+                let truck_info = scale_msg.truck_info();
+                let (mut report, truck_msg) = check_hop::<TruckMessage>(self, &mut tree, timezone, "truck", truck_info.msg_info()).await;
+                if let Some(truck_msg) = &truck_msg{
+                    if !truck_msg.license_plate().eq_ignore_ascii_case(truck_info.license_plate()){
+                        report.add_issue(IssueKind::LinkMismatch, format!("weighing of plate {} refers to the truck {}", truck_info.license_plate(), truck_msg.license_plate()));
+                    }
+                }
+                hops.push(report);
+            }
+        }
+
+        let status = hops.iter().map(|hop| hop.status).max().unwrap_or(HopStatus::Ok);
+        debug!(biocell = %biocell.msg_id(), status = ?status, hops = hops.len(), "provenance verified");
+        VerificationReport{ root: root.channel_info().to_string(), verified_at: current_time_secs(), status, hops }
+    }
+}
+
+//
+// Dates are compared in the timezone of the daily channel, or of the tree when the channel is not part of it
+//
+async fn check_hop<T: CategoryMessage>(resolver: &mut ReferenceResolver, tree: &mut TreeLookup<'_>, timezone: Tz,
+                                       role: &str, reference: &ChannelReference) -> (HopReport, Option<T>){
+    let mut report = HopReport::new(role, T::category().to_string(), reference);
+
+    let message = match resolver.resolve(reference).await{
+        Ok(message) => message,
+        Err(ChannelManagerError::MessageNotFound(msg)) => {
+            report.add_issue(IssueKind::MessageNotFound, format!("message {} not found", msg));
+            return (report, None);
+        }
+        Err(err) => {
+            report.add_issue(IssueKind::ChannelUnreachable, err.to_string());
+            return (report, None);
+        }
+    };
+    let decoded = match message.public_as::<T>(){
+        Ok(decoded) => decoded,
+        Err(err) => {
+            report.add_issue(IssueKind::UndecodableMessage, err.to_string());
+            return (report, None);
+        }
+    };
+    report.message_timestamp = Some(decoded.timestamp());
+    let daily = tree.find(reference.channel_address(), &T::category()).await;
+    let timezone = daily.as_ref().ok().and_then(Option::as_ref).map(|daily| daily.timezone()).unwrap_or(timezone);
+    let message_day = match timestamp_to_day(decoded.timestamp(), &timezone){
+        Some(day) => day,
+        None => {
+            report.add_issue(IssueKind::InvalidTimestamp, format!("timestamp {} is out of the range of the dates", decoded.timestamp()));
+            return (report, None);
+        }
+    };
+    report.message_date = Some(date_to_string(&message_day));
+
+    match daily{
+        Ok(Some(daily)) => {
+            report.category = Some(daily.category().to_string());
+            report.actor_id = Some(daily.actor_id().to_string());
+            report.channel_date = daily.creation_date().ok();
+            if daily.category() != report.expected_category{
+                let detail = format!("published on a {} channel", daily.category());
+                report.add_issue(IssueKind::WrongCategory, detail);
+            }
+            if Some(message_day) != daily.creation_day().ok(){
+                let detail = format!("message of {} on the daily channel of {}", report.message_date.as_deref().unwrap_or("-"), report.channel_date.as_deref().unwrap_or("-"));
+                report.add_issue(IssueKind::DateMismatch, detail);
+            }
+        }
+        Ok(None) => report.add_issue(IssueKind::ChannelNotInTree, format!("channel {} is not a daily channel of the tree", reference.channel_address())),
+        Err(detail) => report.add_issue(IssueKind::ChannelUnreachable, detail)
+    }
+    if let Err(err) = decoded.validate(){
+        report.add_issue(IssueKind::InvalidContent, err.to_string());
+    }
+    (report, Some(decoded))
+}
+
+//
+// Daily channels of the tree loaded only while looking for the channels of the hops: the actors of the expected
+// category first, the other categories only when the channel is not there. Every actor is loaded at most once
+//
+struct TreeLookup<'a>{
+    root: &'a RootChannel,
+    daily: HashMap<String, DailyChannelInfo>,
+    loaded: HashSet<String>,
+    failure: Option<String>,
+}
+
+impl<'a> TreeLookup<'a>{
+    fn new(root: &'a RootChannel) -> Self {
+        TreeLookup { root, daily: HashMap::new(), loaded: HashSet::new(), failure: None }
+    }
+
+    //
+    // None when the channel is not in the tree, an error when it was not found and part of the tree could not be loaded
+    //
+    async fn find(&mut self, address: &str, expected: &Category) -> Result<Option<DailyChannelInfo>, String>{
+        if let Some(daily) = self.daily.get(address){
+            return Ok(Some(daily.clone()));
+        }
+        let mut categories = vec![expected.clone()];
+        categories.extend(self.root.categories().into_iter().filter(|category| category != expected));
+        for category in categories{
+            if self.load_until(address, category).await{
+                return Ok(self.daily.get(address).cloned());
+            }
+        }
+        match &self.failure{
+            None => Ok(None),
+            Some(err) => Err(format!("channel {} not found, the tree could not be read: {}", address, err))
+        }
+    }
+
+    async fn load_until(&mut self, address: &str, category: Category) -> bool{
+        let actors = match self.root.actors_of_category(category.clone()).await{
+            Ok(actors) => actors,
+            Err(err) => {
+                self.failure.get_or_insert(err.to_string());
+                return false;
+            }
+        };
+        for actor in actors{
+            if !self.loaded.insert(format!("{}/{}", category.id(), actor.actor_id())){
+                continue;
+            }
+            match self.root.channels_of_actor(category.clone(), actor.actor_id()).await{
+                Ok(channels) => self.daily.extend(channels.into_iter().map(|daily| (daily.address().to_string(), daily))),
+                Err(err) => {
+                    debug!(category = %category.id(), actor = %actor.actor_id(), error = %err, "actor channel not loaded");
+                    self.failure.get_or_insert(err.to_string());
+                }
+            }
+            if self.daily.contains_key(address){
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use aead::generic_array::GenericArray;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::XChaCha20Poly1305;
+use crate::transport::{StreamsWriter, StreamsReader, Transport, TransportError};
+use crate::utils::hash_string;
+use crate::channels::NetworkConfig;
+
+//
+// Transport that keeps announces, signed packets and writer states in memory.
+// Every writer and reader created by the same instance (or by its clones) shares the same local tangle
+//
+#[derive(Clone)]
+pub struct InMemoryTransport{
+    tangle: Arc<Mutex<LocalTangle>>,
+    counter: Arc<AtomicU64>,
+    network: NetworkConfig,
+}
+
+impl InMemoryTransport{
+    pub fn new() -> Self {
+        InMemoryTransport::with_network(NetworkConfig::devnet())
+    }
+
+    //
+    // The network profile is used only to render explorer urls and to tag the exported states
+    //
+    pub fn with_network(network: NetworkConfig) -> Self {
+        InMemoryTransport { tangle: Arc::new(Mutex::new(LocalTangle::default())), counter: Arc::new(AtomicU64::new(0)), network }
+    }
+}
+
+impl Default for InMemoryTransport{
+    fn default() -> Self {
+        InMemoryTransport::new()
+    }
+}
+
+#[derive(Default)]
+struct LocalTangle{
+    channels: HashMap<String, LocalChannel>,
+}
+
+struct LocalChannel{
+    announce_id: String,
+    psw_hash: String,
+    msgs: Vec<(String, Vec<u8>, Vec<u8>)>,
+}
+
+//
+// The local tangle is only locked for short synchronous sections, never across an await.
+// A poisoned lock is reported as an error instead of propagating the panic
+//
+fn lock_tangle(tangle: &Mutex<LocalTangle>) -> anyhow::Result<MutexGuard<'_, LocalTangle>>{
+    tangle.lock().map_err(|_| anyhow::Error::msg("Local tangle lock poisoned"))
+}
+
+fn next_id(counter: &AtomicU64) -> u64{
+    counter.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+impl LocalTangle{
+
+    fn check_channel(&self, channel_id: &str, announce_id: &str, state_psw: &str) -> anyhow::Result<()>{
+        match self.channels.get(channel_id){
+            Some(ch) if ch.announce_id == announce_id && ch.psw_hash == hash_string(state_psw) => Ok(()),
+            Some(_) => Err(TransportError::WrongPassword.into()),
+            None => Err(anyhow::Error::msg(format!("Channel {}:{} not found", channel_id, announce_id)))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocalWriterState{
+    channel_id: String,
+    announce_id: String,
+    psw_hash: String,
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport{
+    fn create_writer(&self) -> Box<dyn StreamsWriter> {
+        let id = next_id(&self.counter);
+        Box::new(InMemoryWriter{
+            tangle: self.tangle.clone(),
+            counter: self.counter.clone(),
+            channel_id: format!("{:080x}", id),
+            announce_id: String::default(),
+        })
+    }
+
+    fn create_reader(&self, channel_id: &str, announce_id: &str) -> Box<dyn StreamsReader> {
+        Box::new(InMemoryReader{
+            tangle: self.tangle.clone(),
+            channel_id: channel_id.to_string(),
+            announce_id: announce_id.to_string(),
+            cursor: 0,
+        })
+    }
+
+    async fn import_from_tangle(&self, channel_id: &str, announce_id: &str, state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>> {
+        lock_tangle(&self.tangle)?.check_channel(channel_id, announce_id, state_psw)?;
+        Ok(Box::new(InMemoryWriter{
+            tangle: self.tangle.clone(),
+            counter: self.counter.clone(),
+            channel_id: channel_id.to_string(),
+            announce_id: announce_id.to_string(),
+        }))
+    }
+
+    //
+    // As for the tangle, the password only protects the exported state and may differ from the one of the channel
+    //
+    async fn import_from_bytes(&self, bytes: &[u8], state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>> {
+        let state: LocalWriterState = bincode::deserialize(bytes)?;
+        if state.psw_hash != hash_string(state_psw){
+            return Err(TransportError::WrongPassword.into());
+        }
+        match lock_tangle(&self.tangle)?.channels.get(&state.channel_id){
+            Some(ch) if ch.announce_id == state.announce_id => {},
+            _ => return Err(anyhow::Error::msg(format!("Channel {}:{} not found", state.channel_id, state.announce_id)))
+        }
+        Ok(Box::new(InMemoryWriter{
+            tangle: self.tangle.clone(),
+            counter: self.counter.clone(),
+            channel_id: state.channel_id,
+            announce_id: state.announce_id,
+        }))
+    }
+
+    fn network(&self) -> &NetworkConfig {
+        &self.network
+    }
+}
+
+struct InMemoryWriter{
+    tangle: Arc<Mutex<LocalTangle>>,
+    counter: Arc<AtomicU64>,
+    channel_id: String,
+    announce_id: String,
+}
+
+#[async_trait]
+impl StreamsWriter for InMemoryWriter{
+    async fn open_and_save(&mut self, state_psw: &str) -> anyhow::Result<(String, String)> {
+        let mut tangle = lock_tangle(&self.tangle)?;
+        if tangle.channels.contains_key(&self.channel_id){
+            return Err(anyhow::Error::msg("Channel already opened"));
+        }
+        let id = next_id(&self.counter);
+        self.announce_id = format!("{:024x}", id);
+        let channel = LocalChannel{ announce_id: self.announce_id.clone(), psw_hash: hash_string(state_psw), msgs: vec![] };
+        tangle.channels.insert(self.channel_id.clone(), channel);
+        Ok(self.channel_address())
+    }
+
+    async fn send_signed_raw_data(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> anyhow::Result<String> {
+        let m_data = match key_nonce{
+            None => m_data,
+            Some((key, nonce)) => {
+                let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+                chacha.encrypt(GenericArray::from_slice(&nonce), m_data.as_ref())
+                    .map_err(|_| anyhow::Error::msg("Error during masked payload encryption"))?
+            }
+        };
+        let mut tangle = lock_tangle(&self.tangle)?;
+        let id = next_id(&self.counter);
+        let msg_id = format!("{:024x}", id);
+        match tangle.channels.get_mut(&self.channel_id){
+            None => Err(anyhow::Error::msg("Channel not opened yet")),
+            Some(ch) => {
+                ch.msgs.push((msg_id.clone(), p_data, m_data));
+                Ok(msg_id)
+            }
+        }
+    }
+
+    fn export_to_bytes(&self, state_psw: &str) -> anyhow::Result<Vec<u8>> {
+        let state = LocalWriterState{
+            channel_id: self.channel_id.clone(),
+            announce_id: self.announce_id.clone(),
+            psw_hash: hash_string(state_psw),
+        };
+        Ok(bincode::serialize(&state)?)
+    }
+
+    fn channel_address(&self) -> (String, String) {
+        (self.channel_id.clone(), self.announce_id.clone())
+    }
+}
+
+struct InMemoryReader{
+    tangle: Arc<Mutex<LocalTangle>>,
+    channel_id: String,
+    announce_id: String,
+    cursor: usize,
+}
+
+#[async_trait]
+impl StreamsReader for InMemoryReader{
+    async fn attach(&mut self) -> anyhow::Result<()> {
+        let tangle = lock_tangle(&self.tangle)?;
+        match tangle.channels.get(&self.channel_id){
+            Some(ch) if ch.announce_id == self.announce_id => Ok(()),
+            _ => Err(anyhow::Error::msg(format!("Channel {}:{} not found", self.channel_id, self.announce_id)))
+        }
+    }
+
+    async fn fetch_raw_msgs(&mut self) -> anyhow::Result<Vec<(String, Vec<u8>, Vec<u8>)>> {
+        let tangle = lock_tangle(&self.tangle)?;
+        let msgs = match tangle.channels.get(&self.channel_id){
+            None => return Ok(vec![]),
+            Some(ch) => ch.msgs[self.cursor..].to_vec()
+        };
+        self.cursor += msgs.len();
+        Ok(msgs)
+    }
+}
@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+use thiserror::Error;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use iota_streams_lib::payload::payload_serializers::JsonPacket;
+use crate::channels::NetworkConfig;
+use crate::channels::errors::{self, ChannelManagerError};
+
+mod tangle;
+mod in_memory;
+pub use tangle::TangleTransport;
+pub use in_memory::InMemoryTransport;
+
+//
+// Failures with a known cause, the channel tree tells them apart from the other errors of the backends
+//
+#[derive(Debug, Error)]
+pub enum TransportError{
+    #[error("Wrong channel password")]
+    WrongPassword,
+    #[error("Request to the node timed out")]
+    Timeout,
+}
+
+//
+// Writer side of a channel: it is the only way the channel tree publishes something.
+// The password given to open_and_save is the one the writer is imported from the tangle with and can't be changed,
+// the one given to export_to_bytes only protects the exported state
+//
+#[async_trait]
+pub trait StreamsWriter: Send{
+    async fn open_and_save(&mut self, state_psw: &str) -> anyhow::Result<(String, String)>;
+    async fn send_signed_raw_data(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> anyhow::Result<String>;
+    fn export_to_bytes(&self, state_psw: &str) -> anyhow::Result<Vec<u8>>;
+    fn channel_address(&self) -> (String, String);
+}
+
+//
+// Reader side of a channel: every call of fetch_raw_msgs returns only the messages not fetched yet.
+// When fetch_raw_msgs fails the reader should be attached again
+//
+#[async_trait]
+pub trait StreamsReader: Send{
+    async fn attach(&mut self) -> anyhow::Result<()>;
+    async fn fetch_raw_msgs(&mut self) -> anyhow::Result<Vec<(String, Vec<u8>, Vec<u8>)>>;
+}
+
+//
+// Factory of writers and readers used by every layer of the nested channel architecture
+//
+#[async_trait]
+pub trait Transport: Send + Sync{
+    fn create_writer(&self) -> Box<dyn StreamsWriter>;
+    fn create_reader(&self, channel_id: &str, announce_id: &str) -> Box<dyn StreamsReader>;
+    async fn import_from_tangle(&self, channel_id: &str, announce_id: &str, state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>>;
+    async fn import_from_bytes(&self, bytes: &[u8], state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>>;
+    fn network(&self) -> &NetworkConfig;
+}
+
+//
+// Only the failures of the writer and of the reader are transport errors,
+// a payload that can't be encoded or an announcement that can't be decoded are reported as such
+//
+pub(crate) async fn send_public_packet<T: Serialize + Sync>(writer: &mut dyn StreamsWriter, msg: &T) -> errors::Result<String>{
+    let p_data = serde_json::to_vec(msg).map_err(|e| ChannelManagerError::Encoding(e.to_string()))?;
+    Ok(writer.send_signed_raw_data(p_data, vec![], None).await?)
+}
+
+pub(crate) async fn fetch_public_packets<T: DeserializeOwned>(reader: &mut dyn StreamsReader) -> errors::Result<Vec<(String, T)>>{
+    let msgs = reader.fetch_raw_msgs().await?;
+    let mut res = vec![];
+    for (msg_id, p, _) in msgs {
+        let msg = JsonPacket::from_streams_response(&p, &vec![], &None)
+            .and_then(|packet| packet.deserialize_public())
+            .map_err(|e| ChannelManagerError::InvalidMessage(format!("{}: {}", msg_id, e)))?;
+        res.push((msg_id, msg));
+    }
+    Ok(res)
+}
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use iota_streams_lib::channels::{ChannelWriter, ChannelReader, SendOptions};
+use crate::transport::{StreamsWriter, StreamsReader, Transport, TransportError};
+use crate::channels::{NetworkConfig, PowMode};
+use std::future::Future;
+use std::time::Duration;
+
+//
+// Transport backed by the nodes of a network profile.
+// Imports and reader attachments fall back on the next node of the list when a node fails,
+// the permanode and the PoW mode of the profile are passed to every writer and reader
+//
+#[derive(Debug, Clone)]
+pub struct TangleTransport{
+    network: NetworkConfig,
+}
+
+impl TangleTransport{
+    pub fn new(network: NetworkConfig) -> Self {
+        TangleTransport { network }
+    }
+
+    fn local_pow(&self) -> bool {
+        self.network.pow_mode() == PowMode::Local
+    }
+
+    fn send_options(&self, node_url: &str) -> SendOptions{
+        SendOptions{ url: node_url.to_string(), local_pow: self.local_pow() }
+    }
+}
+
+#[async_trait]
+impl Transport for TangleTransport{
+    fn create_writer(&self) -> Box<dyn StreamsWriter> {
+        let mut builder = ChannelWriter::builder()
+            .node(self.network.primary_node())
+            .local_pow(self.local_pow());
+        if let Some(permanode) = self.network.permanode_url(){
+            builder = builder.permanode(permanode);
+        }
+        let writer = builder.build();
+        Box::new(TangleWriter{ writer, timeout: self.network.timeout() })
+    }
+
+    fn create_reader(&self, channel_id: &str, announce_id: &str) -> Box<dyn StreamsReader> {
+        let reader = build_reader(&self.network, self.network.primary_node(), channel_id, announce_id);
+        Box::new(TangleReader{
+            reader,
+            channel_id: channel_id.to_string(),
+            announce_id: announce_id.to_string(),
+            network: self.network.clone()
+        })
+    }
+
+    async fn import_from_tangle(&self, channel_id: &str, announce_id: &str, state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>> {
+        let mut last_err = anyhow::Error::msg("No node configured");
+        for node_url in self.network.node_urls() {
+            let res = with_timeout(
+                self.network.timeout(),
+                ChannelWriter::import_from_tangle(channel_id, announce_id, state_psw, Some(node_url.as_str()), Some(self.send_options(node_url)))
+            ).await;
+            match res.map_err(classify_import_error){
+                Ok(writer) => return Ok(Box::new(TangleWriter{ writer, timeout: self.network.timeout() })),
+                // The other nodes would refuse the password as well
+                Err(err) if matches!(err.downcast_ref(), Some(TransportError::WrongPassword)) => return Err(err),
+                Err(err) => last_err = err
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn import_from_bytes(&self, bytes: &[u8], state_psw: &str) -> anyhow::Result<Box<dyn StreamsWriter>> {
+        let writer = with_timeout(
+            self.network.timeout(),
+            ChannelWriter::import_from_bytes(&bytes.to_vec(), state_psw, Some(self.network.primary_node()), Some(self.send_options(self.network.primary_node())))
+        ).await.map_err(classify_import_error)?;
+        Ok(Box::new(TangleWriter{ writer, timeout: self.network.timeout() }))
+    }
+
+    fn network(&self) -> &NetworkConfig {
+        &self.network
+    }
+}
+
+struct TangleWriter{
+    writer: ChannelWriter,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl StreamsWriter for TangleWriter{
+    async fn open_and_save(&mut self, state_psw: &str) -> anyhow::Result<(String, String)> {
+        with_timeout(self.timeout, self.writer.open_and_save(state_psw)).await
+    }
+
+    async fn send_signed_raw_data(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> anyhow::Result<String> {
+        with_timeout(self.timeout, self.writer.send_signed_raw_data(p_data, m_data, key_nonce)).await
+    }
+
+    fn export_to_bytes(&self, state_psw: &str) -> anyhow::Result<Vec<u8>> {
+        self.writer.export_to_bytes(state_psw)
+    }
+
+    fn channel_address(&self) -> (String, String) {
+        self.writer.channel_address()
+    }
+}
+
+struct TangleReader{
+    reader: ChannelReader,
+    channel_id: String,
+    announce_id: String,
+    network: NetworkConfig,
+}
+
+#[async_trait]
+impl StreamsReader for TangleReader{
+    async fn attach(&mut self) -> anyhow::Result<()> {
+        let mut last_err = anyhow::Error::msg("No node configured");
+        for node_url in self.network.node_urls().clone() {
+            let mut reader = build_reader(&self.network, &node_url, &self.channel_id, &self.announce_id);
+            match with_timeout(self.network.timeout(), reader.attach()).await{
+                Ok(_) => {
+                    self.reader = reader;
+                    return Ok(());
+                },
+                Err(err) => last_err = err
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn fetch_raw_msgs(&mut self) -> anyhow::Result<Vec<(String, Vec<u8>, Vec<u8>)>> {
+        tokio::time::timeout(self.network.timeout(), self.reader.fetch_raw_msgs()).await
+            .map_err(|_| TransportError::Timeout.into())
+    }
+}
+
+//
+// The streams library reports a wrong password only in the message of the error,
+// that is turned into the typed error so that the channel tree can tell it apart
+//
+fn classify_import_error(err: anyhow::Error) -> anyhow::Error{
+    if err.downcast_ref::<TransportError>().is_some(){
+        return err;
+    }
+    let msg = err.to_string().to_lowercase();
+    if msg.contains("password") || msg.contains("decrypt"){
+        return TransportError::WrongPassword.into();
+    }
+    err
+}
+
+fn build_reader(network: &NetworkConfig, node_url: &str, channel_id: &str, announce_id: &str) -> ChannelReader{
+    let mut builder = ChannelReader::builder()
+        .node(node_url);
+    if let Some(permanode) = network.permanode_url(){
+        builder = builder.permanode(permanode);
+    }
+    builder.build(channel_id, announce_id)
+}
+
+async fn with_timeout<T, F>(timeout: Duration, fut: F) -> anyhow::Result<T>
+    where F: Future<Output = anyhow::Result<T>>{
+    tokio::time::timeout(timeout, fut).await
+        .map_err(|_| TransportError::Timeout)?
+}
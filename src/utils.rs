@@ -1,29 +1,70 @@
 pub extern crate serde;
 pub use iota_streams_lib::utility::iota_utility::{create_encryption_nonce, create_encryption_key, hash_string};
-use chrono::{Local, NaiveDateTime, Datelike};
+pub use chrono_tz::Tz;
+pub use chrono::NaiveDate;
+use chrono::{Utc, DateTime, NaiveDateTime, Datelike, TimeZone, Offset};
 
+//
+// Timestamps are always seconds (or millis) since the epoch, they don't depend on the timezone of the machine
+//
 pub fn current_time_millis() -> i64{
-    Local::now().timestamp_millis()
+    Utc::now().timestamp_millis()
 }
 
 pub fn current_time_secs() -> i64{
-    Local::now().timestamp()
+    Utc::now().timestamp()
 }
 
-pub fn timestamp_to_date(timestamp: i64, millis: bool) -> NaiveDateTime{
-    let (sec, nsec): (i64, u32) = if millis{
-        (timestamp / 1000, (timestamp % 1000) as u32 * 1000000)
+//
+// Timestamps read from the tangle are not trusted, the ones out of the range of the dates give None
+//
+pub fn timestamp_to_date(timestamp: i64, millis: bool) -> Option<NaiveDateTime>{
+    let date = if millis{
+        DateTime::from_timestamp_millis(timestamp)
     }else{
-        (timestamp, 0)
+        DateTime::from_timestamp(timestamp, 0)
     };
-    NaiveDateTime::from_timestamp(sec, nsec)
+    date.map(|date| date.naive_utc())
 }
 
-pub fn timestamp_to_date_string(timestamp: i64, millis: bool) -> String{
-    let date = timestamp_to_date(timestamp, millis);
+pub fn timestamp_to_date_string(timestamp: i64, millis: bool) -> Option<String>{
+    let date = timestamp_to_date(timestamp, millis)?;
+    Some(format!("{:02}/{:02}/{}", date.day(), date.month(), date.year()))
+}
+
+//
+// Day the timestamp falls on in the given timezone
+//
+pub fn timestamp_to_day(timestamp: i64, timezone: &Tz) -> Option<NaiveDate>{
+    let utc = timestamp_to_date(timestamp, false)?;
+    let offset = timezone.offset_from_utc_datetime(&utc).fix();
+    utc.checked_add_offset(offset).map(|local| local.date())
+}
+
+pub fn timestamp_to_day_string(timestamp: i64, timezone: &Tz) -> Option<String>{
+    timestamp_to_day(timestamp, timezone).map(|day| date_to_string(&day))
+}
+
+//
+// Dates are shown as dd/mm/yyyy, the format is never parsed back
+//
+pub fn date_to_string(date: &NaiveDate) -> String{
     format!("{:02}/{:02}/{}", date.day(), date.month(), date.year())
 }
 
+//
+// First instant of the day in the given timezone, it is not midnight where the clocks skip it
+//
+pub fn start_of_day(date: NaiveDate, timezone: &Tz) -> Option<i64>{
+    (0..24).filter_map(|hour| date.and_hms_opt(hour, 0, 0))
+        .find_map(|time| timezone.from_local_datetime(&time).earliest())
+        .map(|time| time.timestamp())
+}
+
+pub (crate) fn utc() -> Tz{
+    Tz::UTC
+}
+
 pub fn check_date_format(date: &str) -> bool{
     let re = regex::Regex::new(
         r"^([0-2][0-9]|(3)[0-1])(/)(((0)[0-9])|((1)[0-2]))(/)\d{4}$"
@@ -0,0 +1,37 @@
+use bioenpro4to_channel_manager::transport::{Transport, TransportError, InMemoryTransport};
+
+const PSW: &str = "channel psw";
+
+//
+// create -> send -> read -> import from the tangle and from the exported state, the imported writers keep the channel
+//
+#[tokio::test]
+async fn writer_round_trip(){
+    let transport = InMemoryTransport::new();
+    let mut writer = transport.create_writer();
+    let (channel_id, announce_id) = writer.open_and_save(PSW).await.unwrap();
+    let first = writer.send_signed_raw_data(b"public 1".to_vec(), b"masked 1".to_vec(), None).await.unwrap();
+
+    let mut reader = transport.create_reader(&channel_id, &announce_id);
+    reader.attach().await.unwrap();
+    let msgs = reader.fetch_raw_msgs().await.unwrap();
+    assert_eq!(msgs, vec![(first, b"public 1".to_vec(), b"masked 1".to_vec())]);
+
+    let mut imported = transport.import_from_tangle(&channel_id, &announce_id, PSW).await.unwrap();
+    assert_eq!(imported.channel_address(), (channel_id.clone(), announce_id.clone()));
+    let second = imported.send_signed_raw_data(b"public 2".to_vec(), vec![], None).await.unwrap();
+
+    let state = imported.export_to_bytes("state psw").unwrap();
+    let mut restored = transport.import_from_bytes(&state, "state psw").await.unwrap();
+    let third = restored.send_signed_raw_data(b"public 3".to_vec(), vec![], None).await.unwrap();
+
+    // Only the messages not fetched yet are returned
+    let msgs = reader.fetch_raw_msgs().await.unwrap();
+    let ids: Vec<_> = msgs.into_iter().map(|(msg_id, _, _)| msg_id).collect();
+    assert_eq!(ids, vec![second, third]);
+
+    let err = transport.import_from_tangle(&channel_id, &announce_id, "wrong psw").await.err().unwrap();
+    assert!(matches!(err.downcast_ref(), Some(TransportError::WrongPassword)));
+    let err = transport.import_from_bytes(&state, PSW).await.err().unwrap();
+    assert!(matches!(err.downcast_ref(), Some(TransportError::WrongPassword)));
+}
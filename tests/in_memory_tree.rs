@@ -0,0 +1,222 @@
+use bioenpro4to_channel_manager::channels::root_channel::RootChannel;
+use bioenpro4to_channel_manager::channels::{Category, DailyChannelManager, MessageReader, Keystore, ImportOptions, CachePolicy};
+use bioenpro4to_channel_manager::channels::errors::{self, ChannelManagerError};
+use bioenpro4to_channel_manager::messages::{ChannelReference, ReferenceResolver};
+use bioenpro4to_channel_manager::transport::{Transport, InMemoryTransport};
+use bioenpro4to_channel_manager::utils::{NaiveDate, Tz};
+use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+const TREE_PSW: &str = "tree psw";
+const DAILY_PSW: &str = "daily psw";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Reading{
+    value: u32,
+}
+
+struct TestKeystore;
+
+impl Keystore for TestKeystore{
+    fn tree_password(&self) -> errors::Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(TREE_PSW.to_string()))
+    }
+
+    fn daily_password(&self, _category: &Category, _actor_id: &str) -> errors::Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(DAILY_PSW.to_string()))
+    }
+}
+
+fn date() -> NaiveDate{
+    NaiveDate::from_ymd_opt(2021, 9, 24).unwrap()
+}
+
+//
+// open -> daily channel -> import of the tree -> export and import of the daily channel
+//
+#[tokio::test]
+async fn daily_channel_survives_tree_import_and_state_export(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_timezone(transport.clone(), vec![Category::trucks()], Tz::Europe__Rome);
+    let root_info = root.open(TREE_PSW).await.unwrap();
+
+    let mut daily = root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    daily.send(&Reading{ value: 1 }, &(), None).await.unwrap();
+    let daily_info = daily.channel_info();
+    drop(daily);
+    drop(root);
+
+    let imported = RootChannel::import_from_tangle_with_transport(root_info.channel_id(), root_info.announce_id(), TREE_PSW, transport.clone()).await.unwrap();
+    assert_eq!(imported.timezone(), Tz::Europe__Rome);
+    let channels = imported.channels_of_actor(Category::trucks(), "truck1").await.unwrap();
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0].creation_day().unwrap(), date());
+    assert_eq!(channels[0].address().to_string(), daily_info.to_string());
+
+    let daily = imported.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    assert_eq!(daily.channel_info().to_string(), daily_info.to_string());
+    assert!(matches!(
+        imported.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date().succ_opt().unwrap()).await,
+        Err(ChannelManagerError::DailyChannelNotFound(_))
+    ));
+
+    let state = imported.serialize_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    let mut restored = DailyChannelManager::import_from_base64_with_transport(&state, DAILY_PSW, transport.clone()).await.unwrap();
+    assert_eq!(restored.channel_info().to_string(), daily_info.to_string());
+    assert_eq!(restored.creation_day().unwrap(), date());
+    assert_eq!(restored.timezone(), Tz::Europe__Rome);
+    restored.send(&Reading{ value: 2 }, &(), None).await.unwrap();
+
+    let reader = MessageReader::new_with_transport(&daily_info, transport.clone()).await.unwrap();
+    assert_eq!(reader.msgs_as::<Reading>().unwrap(), vec![Reading{ value: 1 }, Reading{ value: 2 }]);
+
+    assert!(matches!(
+        DailyChannelManager::import_from_base64_with_transport(&state, "wrong psw", transport).await,
+        Err(ChannelManagerError::WrongPassword)
+    ));
+}
+
+//
+// The daily channels in the cache are restored from the backup without reading them from the tangle
+//
+#[tokio::test]
+async fn backup_restores_cached_daily_channels(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    root.open(TREE_PSW).await.unwrap();
+    let daily_info = root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap().channel_info();
+    let backup = root.export_backup(TREE_PSW).await.unwrap();
+    drop(root);
+
+    let restored = RootChannel::restore_backup_with_transport(&backup.bytes, TREE_PSW, transport.clone(), false).await.unwrap();
+    let mut daily = restored.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    assert_eq!(daily.channel_info().to_string(), daily_info.to_string());
+    assert_eq!(restored.cache_stats().await.hits, 1);
+    daily.send(&Reading{ value: 3 }, &(), None).await.unwrap();
+
+    let reader = MessageReader::new_with_transport(&daily_info, transport).await.unwrap();
+    assert_eq!(reader.msgs_as::<Reading>().unwrap(), vec![Reading{ value: 3 }]);
+}
+
+//
+// The daily channels that are not in the cache are saved with the passwords of the keystore,
+// without a keystore they are reported as missing
+//
+#[tokio::test]
+async fn backup_saves_daily_channels_not_in_cache(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    root.open(TREE_PSW).await.unwrap();
+    let daily_info = root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap().channel_info();
+    root.clear_cache().await;
+
+    let backup = root.export_backup(TREE_PSW).await.unwrap();
+    assert!(!backup.is_complete());
+    assert_eq!(backup.missing_daily_channels[0].address().to_string(), daily_info.to_string());
+
+    root.set_keystore(Arc::new(TestKeystore));
+    let backup = root.export_backup(TREE_PSW).await.unwrap();
+    assert!(backup.is_complete());
+    let options = ImportOptions::new().cache_policy(CachePolicy::unbounded());
+    let restored = RootChannel::restore_backup_with_options(&backup.bytes, TREE_PSW, transport, false, options).await.unwrap();
+    let daily = restored.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    assert_eq!(daily.channel_info().to_string(), daily_info.to_string());
+    assert_eq!(restored.cache_stats().await.hits, 1);
+}
+
+//
+// An actor announced after the backup is loaded with the password of the writers on the tangle,
+// even when the backup has been exported with another password
+//
+#[tokio::test]
+async fn actors_loaded_after_restore_use_the_tangle_password(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    root.open(TREE_PSW).await.unwrap();
+    root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    let backup = root.rotate_password(TREE_PSW, "backup psw").await.unwrap();
+    root.new_daily_actor_channel(Category::trucks(), "truck2", DAILY_PSW, date()).await.unwrap();
+    drop(root);
+
+    let restored = RootChannel::restore_backup_with_transport(&backup.bytes, "backup psw", transport, true).await.unwrap();
+    let channels = restored.channels_of_actor(Category::trucks(), "truck2").await.unwrap();
+    assert_eq!(channels.len(), 1);
+}
+
+//
+// After a rotation the old passwords are refused by the tree and by its backups,
+// the writers on the tangle are still imported with the passwords they were opened with
+//
+#[tokio::test]
+async fn rotated_passwords_replace_the_old_ones(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    let root_info = root.open(TREE_PSW).await.unwrap();
+    let daily_info = root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap().channel_info();
+
+    let state = root.rotate_daily_channel_password(Category::trucks(), "truck1", DAILY_PSW, "new daily psw", date()).await.unwrap();
+    DailyChannelManager::import_from_base64_with_transport(&state, "new daily psw", transport.clone()).await.unwrap();
+    assert!(matches!(
+        root.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await,
+        Err(ChannelManagerError::WrongPassword)
+    ));
+    root.clear_cache().await;
+    root.get_daily_actor_channel(Category::trucks(), "truck1", "new daily psw", date()).await.unwrap();
+
+    let backup = root.rotate_password(TREE_PSW, "new tree psw").await.unwrap();
+    assert!(matches!(root.export_backup(TREE_PSW).await, Err(ChannelManagerError::WrongPassword)));
+    assert!(RootChannel::restore_backup_with_transport(&backup.bytes, TREE_PSW, transport.clone(), false).await.is_err());
+
+    let restored = RootChannel::restore_backup_with_transport(&backup.bytes, "new tree psw", transport.clone(), false).await.unwrap();
+    assert!(matches!(
+        restored.get_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await,
+        Err(ChannelManagerError::WrongPassword)
+    ));
+    let daily = restored.get_daily_actor_channel(Category::trucks(), "truck1", "new daily psw", date()).await.unwrap();
+    assert_eq!(daily.channel_info().to_string(), daily_info.to_string());
+    restored.new_daily_actor_channel(Category::trucks(), "truck2", DAILY_PSW, date()).await.unwrap();
+
+    let imported = RootChannel::import_from_tangle_with_transport(root_info.channel_id(), root_info.announce_id(), TREE_PSW, transport).await.unwrap();
+    assert_eq!(imported.channels_of_actor(Category::trucks(), "truck2").await.unwrap().len(), 1);
+}
+
+//
+// A message of the channel that can't be parsed doesn't make the other references to the channel unresolvable
+//
+#[tokio::test]
+async fn corrupt_message_does_not_hide_the_referenced_one(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    root.open(TREE_PSW).await.unwrap();
+    let mut daily = root.new_daily_actor_channel(Category::trucks(), "truck1", DAILY_PSW, date()).await.unwrap();
+    let corrupt_id = daily.send_raw_packet(b"{ not json".to_vec(), vec![], None).await.unwrap();
+    let msg_id = daily.send(&Reading{ value: 4 }, &(), None).await.unwrap();
+    let info = daily.channel_info();
+
+    let mut resolver = ReferenceResolver::new_with_transport(transport);
+    let reference = ChannelReference::new(info.channel_id().to_string(), info.announce_id().to_string(), msg_id);
+    let message = resolver.resolve(&reference).await.unwrap();
+    assert_eq!(message.public_as::<Reading>().unwrap(), Reading{ value: 4 });
+
+    let corrupt = ChannelReference::new(info.channel_id().to_string(), info.announce_id().to_string(), corrupt_id);
+    assert!(matches!(resolver.resolve(&corrupt).await, Err(ChannelManagerError::MessageNotFound(_))));
+}
+
+//
+// Categories are added to an opened tree, a tree is opened only once
+//
+#[tokio::test]
+async fn categories_are_added_after_open(){
+    let transport: Arc<dyn Transport> = Arc::new(InMemoryTransport::new());
+    let tanks = Category::new("tanks", "Tanks", "Tanks");
+    let mut root = RootChannel::new_with_transport(transport.clone(), vec![Category::trucks()]);
+    assert!(matches!(root.add_category(tanks.clone()).await, Err(ChannelManagerError::TreeNotOpened)));
+
+    let root_info = root.open(TREE_PSW).await.unwrap();
+    root.add_category(tanks.clone()).await.unwrap();
+    assert!(matches!(root.open(TREE_PSW).await, Err(ChannelManagerError::TreeAlreadyOpened)));
+
+    let imported = RootChannel::import_from_tangle_with_transport(root_info.channel_id(), root_info.announce_id(), TREE_PSW, transport).await.unwrap();
+    assert_eq!(imported.category("tanks").unwrap(), tanks);
+}